@@ -0,0 +1,50 @@
+use criterion::{criterion_group, Criterion};
+use vector::{
+    event::{
+        metric::{Metric, MetricKind, MetricValue, StatisticKind},
+        Event,
+    },
+    sinks::statsd::encode_event_with_defaults,
+};
+
+fn benchmark_statsd(c: &mut Criterion) {
+    bench_encode(c, "encode counter", 3, MetricValue::Counter { value: 42.0 });
+
+    bench_encode(
+        c,
+        "encode counter with many tags",
+        30,
+        MetricValue::Counter { value: 42.0 },
+    );
+
+    bench_encode(
+        c,
+        "encode distribution",
+        3,
+        MetricValue::Distribution {
+            values: (0..100).map(f64::from).collect(),
+            sample_rates: vec![1; 100],
+            statistic: StatisticKind::Histogram,
+        },
+    );
+}
+
+fn bench_encode(c: &mut Criterion, name: &str, num_tags: usize, value: MetricValue) {
+    let metric = Metric {
+        name: "vector.http.requests".to_owned(),
+        timestamp: None,
+        tags: Some(
+            (0..num_tags)
+                .map(|i| (format!("tag{}", i), format!("value{}", i)))
+                .collect(),
+        ),
+        kind: MetricKind::Incremental,
+        value,
+    };
+
+    c.bench_function(name, |b| {
+        b.iter_with_setup(|| Event::Metric(metric.clone()), encode_event_with_defaults)
+    });
+}
+
+criterion_group!(statsd, benchmark_statsd);
@@ -0,0 +1,106 @@
+use bytes::{Bytes, BytesMut};
+use codec::BytesDelimitedCodec;
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use tokio_util::codec::Decoder;
+
+/// Counts allocations, so the receive buffer strategies can be compared by
+/// how much they allocate as well as by time.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// The UDP source's default `max_length`.
+const MAX_LENGTH: usize = 100 * 1024;
+
+fn datagrams() -> Vec<Vec<u8>> {
+    (0..100)
+        .map(|i| format!("line {}\nanother line {}", i, i).into_bytes())
+        .collect()
+}
+
+fn decode(mut payload: BytesMut, lines: &mut Vec<Bytes>) {
+    let mut decoder = BytesDelimitedCodec::new(b'\n');
+    while let Ok(Some(line)) = decoder.decode_eof(&mut payload) {
+        lines.push(line);
+    }
+}
+
+/// Splits each datagram off the receive buffer, which then has to grow back
+/// to `MAX_LENGTH` for the next one.
+fn split_per_datagram(datagrams: &[Vec<u8>]) -> Vec<Bytes> {
+    let mut lines = Vec::new();
+    let mut buf = BytesMut::with_capacity(MAX_LENGTH);
+    for datagram in datagrams {
+        buf.resize(MAX_LENGTH, 0);
+        buf[..datagram.len()].copy_from_slice(datagram);
+        decode(buf.split_to(datagram.len()), &mut lines);
+    }
+    lines
+}
+
+/// Copies each datagram out of a receive buffer that's reused for all of
+/// them.
+fn copy_from_reused_buffer(datagrams: &[Vec<u8>]) -> Vec<Bytes> {
+    let mut lines = Vec::new();
+    let mut buf = vec![0; MAX_LENGTH];
+    for datagram in datagrams {
+        buf[..datagram.len()].copy_from_slice(datagram);
+        decode(BytesMut::from(&buf[..datagram.len()]), &mut lines);
+    }
+    lines
+}
+
+fn report_allocations(name: &str, datagrams: &[Vec<u8>], receive: fn(&[Vec<u8>]) -> Vec<Bytes>) {
+    let (allocations, bytes) = (
+        ALLOCATIONS.load(Ordering::Relaxed),
+        ALLOCATED_BYTES.load(Ordering::Relaxed),
+    );
+    drop(receive(datagrams));
+    println!(
+        "{}: {} allocations, {} bytes allocated for {} datagrams",
+        name,
+        ALLOCATIONS.load(Ordering::Relaxed) - allocations,
+        ALLOCATED_BYTES.load(Ordering::Relaxed) - bytes,
+        datagrams.len()
+    );
+}
+
+fn benchmark_udp_buffer(c: &mut Criterion) {
+    let datagrams = datagrams();
+    report_allocations("split per datagram", &datagrams, split_per_datagram);
+    report_allocations(
+        "copy from reused buffer",
+        &datagrams,
+        copy_from_reused_buffer,
+    );
+
+    c.bench_function("udp receive: split per datagram", |b| {
+        b.iter(|| split_per_datagram(&datagrams))
+    });
+    c.bench_function("udp receive: copy from reused buffer", |b| {
+        b.iter(|| copy_from_reused_buffer(&datagrams))
+    });
+}
+
+criterion_group!(benches, benchmark_udp_buffer);
+criterion_main!(benches);
@@ -64,37 +64,61 @@ components: sinks: socket: {
 		notices: []
 	}
 
-	configuration: {
-		address: {
-			description: "The address to connect to. The address _must_ include a port."
-			groups: ["tcp", "udp"]
-			required: true
+	configuration: sinks._socket.configuration & {
+		acknowledgement: {
+			common:      false
+			description: "When events sent over UDP are acknowledged to upstream buffers. UDP can't confirm delivery, so in neither case does an acknowledgement mean the event was received."
+			groups: ["udp"]
+			required: false
 			warnings: []
 			type: string: {
-				examples: ["92.12.333.224:5000"]
+				default: "on_send"
+				enum: {
+					on_send:    "Acknowledge an event once its datagram has been handed to the operating system. While the socket can't be connected, the sink applies backpressure."
+					on_enqueue: "Acknowledge an event as soon as it is queued in the sink, holding up to 1024 events while the socket can't be connected. Queued events are lost if Vector stops."
+				}
 			}
 		}
-		mode: {
-			description: "The type of socket to use."
-			groups: ["tcp", "udp", "unix"]
-			required: true
+		backoff: {
+			common:      false
+			description: "How long to wait before reconnecting after the UDP socket can't be connected or a datagram can't be sent. The `n`th retry in a row waits `base ^ n * factor` milliseconds, up to `max_delay_secs`, so the first retry waits 500 milliseconds by default."
+			groups: ["udp"]
+			required: false
 			warnings: []
-			type: string: {
-				enum: {
-					tcp:  "TCP socket"
-					udp:  "UDP socket"
-					unix: "Unix domain socket"
+			type: object: {
+				examples: [{"base": 2, "factor": 50, "max_delay_secs": 10}]
+				options: {
+					base: {
+						description: "The base of the exponential growth of the delay. Must be greater than zero; with `1` every retry waits `factor` milliseconds."
+						required:    false
+						warnings: []
+						type: uint: default: 2
+					}
+					factor: {
+						description: "The multiplier applied to each delay, in milliseconds. Must be greater than zero."
+						required:    false
+						warnings: []
+						type: uint: default: 250
+					}
+					max_delay_secs: {
+						description: "The longest delay. Must be greater than zero."
+						required:    false
+						warnings: []
+						type: uint: {
+							default: 60
+							unit:    "seconds"
+						}
+					}
 				}
 			}
 		}
-		path: {
-			description: "The unix socket path. This should be the absolute path."
-			groups: ["unix"]
-			required: true
+		fail_on_unknown_host: {
+			common:      false
+			description: "When enabled, the sink stops with an error if the host doesn't exist (NXDOMAIN), as that won't resolve itself. Other resolution failures, such as an unreachable DNS server, are always retried with a backoff. When disabled, every resolution failure is retried."
+			groups: ["udp"]
+			required: false
 			warnings: []
-			type: string: {
-				examples: ["/path/to/socket"]
-			}
+			type: bool: default: false
 		}
 	}
 
@@ -103,3 +127,139 @@ components: sinks: socket: {
 		metrics: null
 	}
 }
+
+// The options shared with the sinks built on the batched socket services,
+// which lack the streaming-only ones above.
+components: sinks: _socket: configuration: {
+	address: {
+		description: "The address to connect to. The address _must_ include a port."
+		groups: ["tcp", "udp"]
+		required: true
+		warnings: []
+		type: string: {
+			examples: ["92.12.333.224:5000"]
+		}
+	}
+	detailed_error_sample_rate: {
+		common:      false
+		description: "When set, every this many failed UDP sends are logged in full, with the target address, the error, the datagram's size and the start of its contents. All failures are still counted by the `connection_send_errors_total` internal metric, and otherwise only logged in a rate limited summary."
+		groups: ["udp"]
+		required: false
+		warnings: []
+		type: uint: {
+			default: null
+			examples: [100]
+		}
+	}
+	dns_refresh_interval_secs: {
+		common:      false
+		description: "When set, the host is resolved again and a new socket connected once the current one is this many seconds old, so datagrams follow changes of the host's address. When not set, the host is only resolved again after a failure."
+		groups: ["udp"]
+		required: false
+		warnings: []
+		type: uint: {
+			default: null
+			examples: [30]
+			unit:    "seconds"
+		}
+	}
+	max_datagram_size: {
+		common:      false
+		description: "When set, UDP datagrams larger than this many bytes are counted by the `oversized_datagrams_total` internal metric before being sent. Such datagrams are still sent, but they may be fragmented or dropped on the way. Setting this to the path MTU helps size the batches of sinks like `statsd`."
+		groups: ["udp"]
+		required: false
+		warnings: []
+		type: uint: {
+			default: null
+			examples: [1432]
+			unit:    "bytes"
+		}
+	}
+	mode: {
+		description: "The type of socket to use."
+		groups: ["tcp", "udp", "unix"]
+		required: true
+		warnings: []
+		type: string: {
+			enum: {
+				tcp:  "TCP socket"
+				udp:  "UDP socket"
+				unix: "Unix domain socket"
+			}
+		}
+	}
+	path: {
+		description: "The unix socket path. This should be the absolute path."
+		groups: ["unix"]
+		required: true
+		warnings: []
+		type: string: {
+			examples: ["/path/to/socket"]
+		}
+	}
+	proxy: {
+		common:      false
+		description: "A SOCKS5 proxy to relay datagrams through with a UDP association, for networks where all egress must go through a proxy. The target host is resolved by the proxy. Only proxies without authentication are supported, and `startup_probe` is skipped when a proxy is used."
+		groups: ["udp"]
+		required: false
+		warnings: []
+		type: object: {
+			examples: [{"address": "socks.example.com:1080"}]
+			options: {
+				address: {
+					description: "The `host:port` of the SOCKS5 proxy."
+					required:    true
+					warnings: []
+					type: string: examples: ["socks.example.com:1080"]
+				}
+			}
+		}
+	}
+	round_robin: {
+		common:      false
+		description: "When enabled, each datagram is sent to the next of the addresses the host resolves to, spreading load across DNS-based load balancers. A socket is kept per address, and the host is only resolved again after `dns_refresh_interval_secs` or a failure. When disabled, the first resolved address is always used."
+		groups: ["udp"]
+		required: false
+		warnings: []
+		type: bool: default: false
+	}
+	send_buffer_bytes: {
+		common:      false
+		description: "The size of the kernel send buffer (`SO_SNDBUF`) of the UDP socket. A larger buffer absorbs bursts that would otherwise be dropped silently. The OS may cap the size, in which case a debug message is logged and the socket is used as is. By default the OS default is kept."
+		groups: ["udp"]
+		required: false
+		warnings: []
+		type: uint: {
+			default: null
+			examples: [4194304]
+			unit:    "bytes"
+		}
+	}
+	srv: {
+		common:      false
+		description: "When enabled, `address` is the name of a DNS SRV record, such as `_statsd._udp.example.com`, instead of a `host:port`. The record is looked up on every connect, and gives both the host and the port to send to. Among the records with the lowest priority, one is picked at random in proportion to its weight. Records with the target `.`, meaning the service is unavailable, are ignored."
+		groups: ["udp"]
+		required: false
+		warnings: []
+		type: bool: default: false
+	}
+	startup_probe: {
+		common:      false
+		description: "When enabled, the healthcheck sends an empty datagram and fails if it is rejected with an ICMP port unreachable error. A passing probe does not guarantee delivery, as the error may be filtered by the network."
+		groups: ["udp"]
+		required: false
+		warnings: []
+		type: bool: default: false
+	}
+	static_hosts: {
+		common:      false
+		description: "A map of host names to fixed IP addresses. A host listed here is never resolved through DNS, much like an entry in `/etc/hosts`."
+		groups: ["udp"]
+		required: false
+		warnings: []
+		type: object: {
+			examples: [{"statsd.internal": "10.0.0.5"}]
+			options: {}
+		}
+	}
+}
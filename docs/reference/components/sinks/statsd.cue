@@ -48,10 +48,205 @@ components: sinks: statsd: {
 		}
 	}
 
-	configuration: sinks.socket.configuration & {
+	configuration: sinks._socket.configuration & {
+		absolute_to_incremental: {
+			common:      false
+			description: "When enabled, absolute counters, histograms and summaries are sent as what was added to them since the previous value of the same series, which is what StatsD receivers expect. The first value of each series only sets the baseline and isn't sent, and a series not seen for 15 minutes starts over. When disabled, absolute histograms are dropped, as their cumulative bucket counts would be counted again on every flush."
+			required:    false
+			warnings: []
+			type: bool: default: false
+		}
+		adaptive_timeout: {
+			common:      false
+			description: "When enabled, the batch timeout shrinks in proportion to how full the batch is, so a nearly full batch is flushed well before `batch.timeout_secs` while a nearly empty one waits for most of it. This lowers latency under load without sending many small batches when traffic is light."
+			required:    false
+			warnings: []
+			type: bool: default: false
+		}
+		align_batch_timeout: {
+			common:      false
+			description: "When enabled, batches are flushed on wall-clock multiples of `batch.timeout_secs` (for example at :00, :10, :20 with a 10 second timeout), instead of `batch.timeout_secs` after their first metric. This keeps metrics within the aggregation windows of receivers that align them to the clock."
+			required:    false
+			warnings: []
+			type: bool: default: false
+		}
+		batch_disabled: {
+			common:      false
+			description: "When enabled, each metric is sent in a packet of its own as soon as it's encoded, instead of being batched. This lowers latency at the cost of throughput, and `batch.max_events` is ignored."
+			required:    false
+			warnings: []
+			type: bool: default: false
+		}
+		connect_timeout_secs: {
+			common:      false
+			description: "The maximum time to wait for a TCP connection to be established, including DNS resolution and the TLS handshake. When it elapses the batch fails like any other send error. By default there is no limit."
+			groups: ["tcp"]
+			required: false
+			warnings: []
+			type: uint: {
+				default: null
+				unit:    "seconds"
+			}
+		}
+		connection_mode: {
+			common:      false
+			description: "Whether batches share one TCP connection or each get their own. Some relays prefer long-lived connections, others frequent reconnects."
+			groups: ["tcp"]
+			required: false
+			warnings: []
+			type: string: {
+				default: "per_batch"
+				enum: {
+					per_batch:  "Open a new connection for every batch, unless `idle_timeout_secs` keeps the previous one open."
+					persistent: "Keep one connection open, with TCP keepalive, and only reconnect after it fails. `idle_timeout_secs` is ignored."
+				}
+			}
+		}
+		default_sample_rates: {
+			common:      false
+			description: "Sample rates, between 0 and 1, applied to metrics that don't carry their own. Counter values are multiplied by their rate, so the receiver's scaling restores the original value. Distribution samples without an explicit rate are sent with this rate."
+			required:    false
+			warnings: []
+			type: object: {
+				examples: [{"counter": 1.0, "distribution": 0.1}]
+				options: {}
+			}
+		}
+		dialect: {
+			common:      false
+			description: "The flavor of the StatsD protocol spoken by the receiver."
+			required:    false
+			warnings: []
+			type: string: {
+				default: "statsd"
+				enum: {
+					statsd:    "Classic StatsD. Tag values are sent as-is."
+					dogstatsd: "DogStatsD. Characters not allowed in Datadog tag values are percent-encoded."
+				}
+			}
+		}
+		disk_fallback_max_bytes: {
+			common:      false
+			description: "With `disk_fallback_path` set, the most bytes the disk fallback file may hold. The oldest batches are dropped to make room for new ones, and counted by the `spilled_batches_dropped_total` internal metric."
+			required:    false
+			warnings: []
+			type: uint: {
+				default: 104857600
+				unit:    "bytes"
+			}
+		}
+		disk_fallback_path: {
+			common:      false
+			description: "When set, batches that fail to send are appended to this file instead of being dropped, and are periodically resent until they are delivered. Batches still in the file are also resent after a restart. The file is never rewritten in place, so no batch is lost if Vector crashes while resending them."
+			required:    false
+			warnings: []
+			type: string: {
+				default: null
+				examples: ["/var/lib/vector/statsd.spill"]
+			}
+		}
+		empty_name_placeholder: {
+			common:      false
+			description: "The name given to metrics with an empty name, which would otherwise corrupt the packet. When not set, such metrics are dropped and counted by the `encode_errors_total` internal metric."
+			required:    false
+			warnings: []
+			type: string: {
+				default: null
+				examples: ["unnamed"]
+			}
+		}
+		entity_id_tag: {
+			common:      false
+			description: "A tag holding the id of the entity, such as a container, that a metric comes from. With the `dogstatsd` dialect, its value is sent in the entity id extension (`|e:<id>`) for DogStatsD's origin detection, and the tag itself is not sent. Metrics without the tag are sent without the extension. Ignored with the `statsd` dialect."
+			required:    false
+			warnings: []
+			type: string: {
+				default: null
+				examples: ["container_id"]
+			}
+		}
+		fallback: {
+			common:      false
+			description: "An alternative socket, configured with the same options as the sink itself, that is used instead when the configured one can't be connected to at startup. This is useful to fall back to a TCP address when a Unix socket doesn't exist."
+			required:    false
+			warnings: []
+			type: object: {
+				examples: [{"mode": "tcp", "address": "127.0.0.1:8125"}]
+				options: {}
+			}
+		}
+		idle_flush_ms: {
+			common:      false
+			description: "When set, a partial batch is flushed once no new metric has arrived for this many milliseconds, instead of waiting for `batch.timeout_secs`. This cuts the latency of the last metrics of a burst. Should be shorter than `batch.timeout_secs` to have any effect."
+			required:    false
+			warnings: []
+			type: uint: {
+				default: null
+				unit:    "milliseconds"
+			}
+		}
+		idle_timeout_secs: {
+			common:      false
+			description: "With `connection_mode` set to `per_batch`, when set, the TCP connection is kept open between batches and closed after it has been idle for this many seconds. The next batch opens a new connection. By default a new connection is opened for every batch."
+			groups: ["tcp"]
+			required: false
+			warnings: []
+			type: uint: {
+				default: null
+				unit:    "seconds"
+			}
+		}
+		max_buffered_bytes: {
+			common:      false
+			description: "With `when_no_reader` set to `buffer`, the most bytes of batches kept for the next reader of the named pipe. The oldest batches are dropped first, and counted by the `events_dropped_total` internal metric."
+			groups: ["fifo"]
+			required: false
+			warnings: []
+			type: uint: {
+				default: 1048576
+				unit:    "bytes"
+			}
+		}
+		max_packets_per_flush: {
+			common:      false
+			description: "When set, at most this many packets are sent per `batch.timeout_secs`, spaced evenly over it, instead of back to back. This keeps a burst of metrics from flooding the receiver. Metrics that don't fit wait in the sink and go out with later packets. Must be greater than zero."
+			required:    false
+			warnings: []
+			type: uint: {
+				default: null
+				unit:    null
+			}
+		}
+		mode: type: string: enum: fifo: "Named pipe (FIFO), written at `path`. The pipe must already exist, for example created with `mkfifo`."
+		name_rewrites: {
+			common:      false
+			description: "Regular expression rewrites applied to metric names, in order, before the namespace is added. Every match of `pattern` is replaced with `replacement`, which may refer to capture groups like `$1`. This can strip dynamic segments, such as IDs, to reduce cardinality. Invalid patterns are rejected at startup."
+			required:    false
+			warnings: []
+			type: array: {
+				default: []
+				items: type: object: {
+					examples: [{"pattern": "\\.\\d+\\.", "replacement": "."}]
+					options: {
+						pattern: {
+							description: "The regular expression to match in metric names."
+							required:    true
+							warnings: []
+							type: string: examples: ["\\.\\d+\\."]
+						}
+						replacement: {
+							description: "What each match is replaced with."
+							required:    true
+							warnings: []
+							type: string: examples: ["."]
+						}
+					}
+				}
+			}
+		}
 		namespace: {
 			common:      true
-			description: "A prefix that will be added to all metric names."
+			description: "A prefix that will be added to all metric names. Metrics carrying a `__no_namespace` tag, whose names are already fully qualified, are sent without it, and the tag itself is not sent."
 			required:    false
 			warnings: []
 			type: string: {
@@ -59,5 +254,178 @@ components: sinks: statsd: {
 				examples: ["service"]
 			}
 		}
+		namespace_separator: {
+			common:      false
+			description: "The separator between the namespace and the metric name."
+			required:    false
+			warnings: []
+			type: string: {
+				default: "."
+				examples: ["_"]
+			}
+		}
+		namespace_tag: {
+			common:      false
+			description: "The name of a metric tag holding a per-metric namespace. When a metric carries this tag, its value is used as the prefix instead of `namespace`, and the tag itself is not sent. Metrics without the tag fall back to `namespace`."
+			required:    false
+			warnings: []
+			type: string: {
+				default: null
+				examples: ["__namespace"]
+			}
+		}
+		overflow_policy: {
+			common:      false
+			description: "What to do with new metrics while the batch buffer is full and can't be sent yet. Dropped metrics are counted by the `events_dropped_total` internal metric."
+			required:    false
+			warnings: []
+			type: string: {
+				default: "block"
+				enum: {
+					block:       "Apply back pressure to upstream components until the batch is sent."
+					drop_newest: "Drop the new metric."
+					drop_oldest: "Drop the full batch and start a new one with the new metric."
+				}
+			}
+		}
+		priority: {
+			common:      false
+			description: "The socket priority (`SO_PRIORITY`) of the TCP connection, which selects the queue its packets take on the outgoing interface. Only supported on Linux; elsewhere a warning is logged and the connection is used as is."
+			groups: ["tcp"]
+			required: false
+			warnings: []
+			type: uint: {
+				default: null
+				examples: [4]
+			}
+		}
+		sample_rate: {
+			common:      false
+			description: "The fraction of counters and timers to send, chosen at random. Sent metrics carry the rate (`|@rate`) for the receiver to scale their values back up. Gauges and sets are always sent. Must be greater than 0 and at most 1."
+			required:    false
+			warnings: []
+			type: float: {
+				default: null
+				examples: [0.1]
+			}
+		}
+		set_emission: {
+			common:      false
+			description: "How sets are sent to the receiver."
+			required:    false
+			warnings: []
+			type: string: {
+				default: "members"
+				enum: {
+					members: "A set line (`|s`) for each member of the set."
+					count:   "A single gauge holding the number of members in the set, for receivers that only need its cardinality."
+				}
+			}
+		}
+		soft_max_bytes: {
+			common:      false
+			description: "When set, a batch is flushed as soon as it holds this many bytes, instead of waiting until it reaches `batch.max_bytes`. This keeps less data buffered in memory under backpressure. Must not exceed `batch.max_bytes`."
+			required:    false
+			warnings: []
+			type: uint: {
+				default: null
+				unit:    "bytes"
+			}
+		}
+		sort_by_name: {
+			common:      false
+			description: "When enabled, the lines of each packet are sorted by metric name instead of being sent in the order the metrics arrived. This makes the output deterministic."
+			required:    false
+			warnings: []
+			type: bool: default: false
+		}
+		sort_by_type: {
+			common:      false
+			description: "When enabled, the lines of each packet are ordered by metric type: counters first, then gauges, then distributions, then sets. Metrics of the same type keep their order. This helps servers that apply a packet's lines in order, such as those deriving values from counters before gauges."
+			required:    false
+			warnings: []
+			type: bool: default: false
+		}
+		summary_quantile_name: {
+			common:      false
+			description: "Summaries are sent as a gauge per quantile, plus `<name>.count` and `<name>.sum` counters. This is the name of the quantile gauges, where `{name}` is replaced by the summary's name and `{quantile}` by the quantile as a percentile, such as `50` or `99.9`."
+			required:    false
+			warnings: []
+			type: string: {
+				default: "{name}.q{quantile}"
+				examples: ["{name}.q{quantile}", "{name}.p{quantile}"]
+			}
+		}
+		suppress_zero_counters: {
+			common:      false
+			description: "When enabled, incremental counters with a value of zero are dropped instead of sent, and counted by the `events_suppressed_total` internal metric."
+			required:    false
+			warnings: []
+			type: bool: default: false
+		}
+		tag_prefix: {
+			common:      false
+			description: "The characters that introduce the tag block of each line, as in `|#env:prod`. Some non-standard collectors expect `T` or no prefix at all."
+			required:    false
+			warnings: []
+			type: string: {
+				default: "#"
+				examples: ["#", "T", ""]
+			}
+		}
+		tos: {
+			common:      false
+			description: "The type of service byte, or traffic class for IPv6, that marks the packets of the TCP connection, so that metrics traffic can be prioritized or deprioritized on congested links. The DSCP value goes in its upper six bits, so `184` marks packets as Expedited Forwarding. When it can't be set, a warning is logged and the connection is used unmarked."
+			groups: ["tcp"]
+			required: false
+			warnings: []
+			type: uint: {
+				default: null
+				examples: [184]
+			}
+		}
+		unchanged_gauge_interval_secs: {
+			common:      false
+			description: "When set, an absolute gauge whose value is the same as the one last sent for its series is dropped, unless this many seconds have passed since that one was sent. This saves bandwidth on frequently sampled gauges while still refreshing them regularly. Dropped gauges are counted by the `events_suppressed_total` internal metric."
+			required:    false
+			warnings: []
+			type: uint: {
+				default: null
+				examples: [60]
+				unit:    "seconds"
+			}
+		}
+		value_delimiter: {
+			common:      false
+			description: "The character placed between a metric's name and its value. Some non-standard collectors expect `=` instead of `:`."
+			required:    false
+			warnings: []
+			type: string: {
+				default: ":"
+				examples: [":", "="]
+			}
+		}
+		when_no_reader: {
+			common:      false
+			description: "What happens to batches written while no reader has the named pipe open. Dropped batches are counted by the `events_dropped_total` internal metric."
+			groups: ["fifo"]
+			required: false
+			warnings: []
+			type: string: {
+				default: "drop"
+				enum: {
+					drop:   "Drop the batch."
+					buffer: "Keep the batch in memory, up to `max_buffered_bytes`, and write it once a reader opens the pipe."
+				}
+			}
+		}
+		write_coalescing: {
+			common:      false
+			description: "Whether each batch of metrics is written to the TCP connection as a single buffer. When disabled, every metric line is written separately."
+			groups: ["tcp"]
+			required: false
+			warnings: []
+			type: bool: default: true
+		}
 	}
 }
@@ -51,12 +51,113 @@ components: sources: prometheus: {
 	}
 
 	configuration: {
+		accept_protobuf: {
+			common:      false
+			description: "When enabled, Vector asks targets for the protobuf exposition format, falling back to text for targets that don't support it. Responses are parsed according to their `Content-Type` either way."
+			required:    false
+			warnings: []
+			type: bool: default: false
+		}
+		emit_staleness_markers: {
+			common:      false
+			description: "When enabled, a series that an endpoint exposed on its previous scrape but no longer does is reported once with a staleness marker: a gauge with the series' name and tags and a `NaN` value, like Prometheus's own markers."
+			required:    false
+			warnings: []
+			type: bool: default: false
+		}
 		endpoints: {
-			description: "Endpoints to scrape metrics from."
+			description: "Endpoints to scrape metrics from. On Unix, an endpoint of the form `unix://<path>` is scraped at `/metrics` over the Unix socket at `<path>`. Other endpoints must use the `http` or `https` scheme, and can't contain credentials (`user:password@`), which wouldn't be sent and would leak into logs."
 			required:    true
 			warnings: ["You must explicitly add the path to your endpoints. Vector will _not_ automatically add `/metics`."]
 			type: array: {
-				items: type: string: examples: ["http://localhost:9090/metrics"]
+				items: type: string: examples: ["http://localhost:9090/metrics", "unix:///var/run/exporter.sock"]
+			}
+		}
+		exclude_metrics: {
+			common:      false
+			description: "Regular expressions matched against the name of every scraped metric. Metrics matching any of them are dropped before they are emitted."
+			required:    false
+			warnings: []
+			type: array: {
+				default: []
+				items: type: string: examples: ["^go_", "_bucket$"]
+			}
+		}
+		histograms_as_distributions: {
+			common:      false
+			description: "When enabled, scraped histograms are converted to distributions, so they can be sent to sinks that don't support aggregated histograms. Each non-empty bucket becomes one sample at the bucket's midpoint, with the bucket's count as its sample rate. The counts remain cumulative since the target started."
+			required:    false
+			warnings: []
+			type: bool: default: false
+		}
+		include_metrics: {
+			common:      false
+			description: "Regular expressions matched against the name of every scraped metric. When set, only metrics matching at least one of them are emitted. `exclude_metrics` is applied afterwards."
+			required:    false
+			warnings: []
+			type: array: {
+				default: []
+				items: type: string: examples: ["^http_"]
+			}
+		}
+		max_backoff_secs: {
+			common:      false
+			description: "The maximum interval between scrapes of an endpoint that keeps failing, in seconds. After each consecutive failed scrape the interval for that endpoint is doubled, up to this bound, and it is reset on the first successful scrape."
+			required:    false
+			warnings: []
+			type: uint: {
+				default: 300
+				unit:    "seconds"
+			}
+		}
+		max_concurrent_scrapes: {
+			common:      false
+			description: "The maximum number of scrape requests in flight at once. Scrapes beyond this limit wait for an earlier one to complete, and the number waiting is reported by the `scrape_queue_depth` internal metric. A queue that stays deep means scrapes can't keep up with `scrape_interval_secs`."
+			required:    false
+			warnings: []
+			type: uint: {
+				default: 10
+				unit:    null
+			}
+		}
+		max_redirects: {
+			common:      false
+			description: "The maximum number of redirects followed for a single scrape. Redirects are not followed by default, so a redirecting endpoint is reported as an error response."
+			required:    false
+			warnings: []
+			type: uint: {
+				default: 0
+				unit:    null
+			}
+		}
+		user_agent: {
+			common:      false
+			description: "The `User-Agent` header sent with scrape requests. Defaults to `Vector/<version>`."
+			required:    false
+			warnings: []
+			type: string: {
+				default: null
+				examples: ["my-scraper/1.0"]
+			}
+		}
+		type_tag: {
+			common:      false
+			description: "When set, every scraped metric is tagged with its Prometheus type (`counter`, `gauge`, `histogram`, `summary` or `untyped`) under this tag name."
+			required:    false
+			warnings: []
+			type: string: {
+				default: null
+				examples: ["prometheus_type"]
+			}
+		}
+		unit_tag: {
+			common:      false
+			description: "When set, every scraped metric whose name ends with a unit, following the Prometheus naming conventions (for example `request_duration_seconds` or `response_size_bytes_total`), is tagged with that unit under this tag name. Sinks that send tags, like `statsd`, then carry the unit downstream."
+			required:    false
+			warnings: []
+			type: string: {
+				default: null
+				examples: ["unit"]
 			}
 		}
 		scrape_interval_secs: {
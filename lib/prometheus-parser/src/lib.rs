@@ -29,6 +29,9 @@ pub enum ParserError {
 
     #[snafu(display("expected value in range [0, {}], found: {}", u32::MAX, value))]
     ValueOutOfRange { value: f64 },
+
+    #[snafu(display("invalid protobuf payload: {}", message))]
+    InvalidProtobuf { message: String },
 }
 
 #[derive(Debug, PartialEq)]
@@ -22,8 +22,8 @@ use crate::{api, internal_events::ApiStarted};
 use crate::service;
 
 use crate::internal_events::{
-    VectorConfigLoadFailed, VectorQuit, VectorRecoveryFailed, VectorReloadFailed, VectorReloaded,
-    VectorStarted, VectorStopped,
+    set_sample_rate, VectorConfigLoadFailed, VectorQuit, VectorRecoveryFailed, VectorReloadFailed,
+    VectorReloaded, VectorStarted, VectorStopped,
 };
 use tokio::runtime;
 use tokio::runtime::Runtime;
@@ -150,6 +150,9 @@ impl Application {
                 config::LOG_SCHEMA
                     .set(config.global.log_schema.clone())
                     .expect("Couldn't set schema");
+                if let Some(rate) = config.global.internal_event_sample_rate {
+                    set_sample_rate(rate);
+                }
 
                 let diff = config::ConfigDiff::initial(&config);
                 let pieces = topology::build_or_log_errors(&config, &diff)
@@ -229,6 +232,9 @@ impl Application {
                                 .await
                             {
                                 Ok(true) => {
+                                    // Unset falls back to emitting every event.
+                                    set_sample_rate(topology.config().global.internal_event_sample_rate.unwrap_or(1));
+
                                     #[cfg(feature="api")]
                                     if let Some(ref api_server) = api_server {
                                         api_server.update_config(topology.config())
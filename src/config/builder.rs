@@ -96,6 +96,17 @@ impl ConfigBuilder {
             errors.push("conflicting values for 'data_dir' found".to_owned());
         }
 
+        match (
+            self.global.internal_event_sample_rate,
+            with.global.internal_event_sample_rate,
+        ) {
+            (None, rate) => self.global.internal_event_sample_rate = rate,
+            (Some(rate), Some(other)) if rate != other => {
+                errors.push("conflicting values for 'internal_event_sample_rate' found".to_owned());
+            }
+            _ => (),
+        }
+
         // If the user has multiple config files, we must *merge* log schemas until we meet a
         // conflict, then we are allowed to error.
         if let Err(merge_errors) = self.global.log_schema.merge(with.global.log_schema) {
@@ -50,6 +50,11 @@ pub struct GlobalOptions {
         default
     )]
     pub log_schema: LogSchema,
+    /// Emit the metrics of high-frequency internal events, like each event
+    /// a socket source receives, for only one in this many of them, scaled
+    /// up to stand for the rest.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub internal_event_sample_rate: Option<u64>,
 }
 
 pub fn default_data_dir() -> Option<PathBuf> {
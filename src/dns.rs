@@ -2,8 +2,11 @@ use futures::{future::BoxFuture, FutureExt, TryFutureExt};
 use futures01::Future;
 use hyper::client::connect::dns::Name as Name13;
 use snafu::ResultExt;
+#[cfg(not(unix))]
+use std::net::ToSocketAddrs;
 use std::{
-    net::{IpAddr, Ipv4Addr, SocketAddr, ToSocketAddrs},
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
     task::{Context, Poll},
 };
 use tokio::task::spawn_blocking;
@@ -45,16 +48,90 @@ impl Resolver {
                     }
                     name => name,
                 };
-                (name_ref, dummy_port).to_socket_addrs()
+                resolve(name_ref, dummy_port)
             })
             .await
             .context(JoinError)?
-            .map(LookupIp)
-            .context(UnableLookup)
+            .map(|addresses| LookupIp(addresses.into_iter()))
         }
     }
 }
 
+/// Resolves `name` with the system resolver, telling a name that doesn't
+/// exist apart from other failures by the resolver's error code, which the
+/// standard library only turns into a message.
+#[cfg(unix)]
+fn resolve(name: &str, port: u16) -> Result<Vec<SocketAddr>, DnsError> {
+    use nix::libc;
+    use std::{ffi::CStr, ffi::CString, mem, ptr};
+
+    let c_name = CString::new(name).map_err(|error| DnsError::UnableLookup {
+        source: io::Error::new(io::ErrorKind::InvalidInput, error),
+    })?;
+    let mut hints: libc::addrinfo = unsafe { mem::zeroed() };
+    hints.ai_socktype = libc::SOCK_STREAM;
+    let mut list = ptr::null_mut();
+    let code = unsafe { libc::getaddrinfo(c_name.as_ptr(), ptr::null(), &hints, &mut list) };
+    match code {
+        0 => (),
+        libc::EAI_NONAME => return NotFound { name }.fail(),
+        #[cfg(all(target_os = "linux", target_env = "gnu"))]
+        libc::EAI_NODATA => return NotFound { name }.fail(),
+        libc::EAI_SYSTEM => return Err(io::Error::last_os_error()).context(UnableLookup),
+        code => {
+            let message = unsafe { CStr::from_ptr(libc::gai_strerror(code)) };
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!(
+                    "failed to lookup address information: {}",
+                    message.to_string_lossy()
+                ),
+            ))
+            .context(UnableLookup);
+        }
+    }
+
+    let mut addresses = Vec::new();
+    let mut entry = list;
+    while !entry.is_null() {
+        let info = unsafe { &*entry };
+        match info.ai_family {
+            libc::AF_INET => {
+                let address = unsafe { &*(info.ai_addr as *const libc::sockaddr_in) };
+                let ip = Ipv4Addr::from(u32::from_be(address.sin_addr.s_addr));
+                addresses.push(SocketAddr::new(ip.into(), port));
+            }
+            libc::AF_INET6 => {
+                let address = unsafe { &*(info.ai_addr as *const libc::sockaddr_in6) };
+                let ip = Ipv6Addr::from(address.sin6_addr.s6_addr);
+                addresses.push(SocketAddr::new(ip.into(), port));
+            }
+            _ => (),
+        }
+        entry = info.ai_next;
+    }
+    unsafe { libc::freeaddrinfo(list) };
+    Ok(addresses)
+}
+
+#[cfg(not(unix))]
+fn resolve(name: &str, port: u16) -> Result<Vec<SocketAddr>, DnsError> {
+    // WSAHOST_NOT_FOUND and WSANO_DATA, which the standard library keeps as
+    // the error's OS code.
+    const NOT_FOUND: &[i32] = &[11001, 11004];
+    match (name, port).to_socket_addrs() {
+        Ok(addresses) => Ok(addresses.collect()),
+        Err(error)
+            if error
+                .raw_os_error()
+                .map_or(false, |code| NOT_FOUND.contains(&code)) =>
+        {
+            NotFound { name }.fail()
+        }
+        Err(error) => Err(error).context(UnableLookup),
+    }
+}
+
 impl Iterator for LookupIp {
     type Item = IpAddr;
 
@@ -81,10 +158,20 @@ impl Service<Name13> for Resolver {
 pub enum DnsError {
     #[snafu(display("Unable to resolve name: {}", source))]
     UnableLookup { source: tokio::io::Error },
+    #[snafu(display("Unable to resolve name: {} doesn't exist", name))]
+    NotFound { name: String },
     #[snafu(display("Failed to join with resolving future: {}", source))]
     JoinError { source: tokio::task::JoinError },
 }
 
+impl DnsError {
+    /// Whether the name doesn't exist (NXDOMAIN), as opposed to a failure
+    /// that may clear up by itself, like an unreachable or failing server.
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, DnsError::NotFound { .. })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::Resolver;
@@ -113,4 +200,15 @@ mod tests {
     async fn resolve_ipv6() {
         assert!(resolve("::1").await);
     }
+
+    #[tokio::test]
+    async fn unknown_host_is_not_found() {
+        // `.invalid` names never exist (RFC 6761).
+        let error = Resolver
+            .lookup_ip("nonexistent.invalid".to_owned())
+            .await
+            .err()
+            .unwrap();
+        assert!(error.is_not_found(), "{}", error);
+    }
 }
@@ -248,6 +248,36 @@ impl Metric {
             .filter(|t| t.get(name).filter(|v| *v == value).is_some())
             .is_some()
     }
+
+    /// Estimates the size of this metric encoded as StatsD lines, without
+    /// a namespace, for pre-sizing buffers. Numbers are assumed to format
+    /// to at most 24 characters, which all but extreme values do, and tag
+    /// values to need no escaping.
+    pub fn estimated_statsd_size(&self) -> usize {
+        // A number, as a value or sample rate.
+        const NUMBER: usize = 24;
+        // The separators around a value, the longest type, the sample
+        // rate's prefix, the tags' prefix and the newline.
+        const OVERHEAD: usize = ":|ms|@|#\n".len() + NUMBER;
+
+        let tags = self.tags.as_ref().map_or(0, |tags| {
+            tags.iter()
+                .map(|(name, value)| name.len() + value.len() + ":,".len())
+                .sum()
+        });
+        let line = self.name.len() + OVERHEAD + tags;
+        match &self.value {
+            MetricValue::Counter { .. } | MetricValue::Gauge { .. } => line + NUMBER,
+            MetricValue::Set { values } => values.iter().map(|value| line + value.len()).sum(),
+            MetricValue::Distribution { values, .. } => values.len() * (line + NUMBER),
+            MetricValue::AggregatedHistogram { buckets, .. } => buckets.len() * (line + NUMBER),
+            // A quantile's suffix, like `.q99.9`, and the `count` and `sum`
+            // lines.
+            MetricValue::AggregatedSummary { quantiles, .. } => {
+                (quantiles.len() + 2) * (line + NUMBER + NUMBER)
+            }
+        }
+    }
 }
 
 impl Display for Metric {
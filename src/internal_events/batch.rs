@@ -0,0 +1,23 @@
+use super::InternalEvent;
+use metrics::counter;
+
+#[derive(Debug)]
+pub struct BatchEventsDropped {
+    pub count: usize,
+    pub policy: &'static str,
+}
+
+impl InternalEvent for BatchEventsDropped {
+    fn emit_logs(&self) {
+        warn!(
+            message = "Batch buffer full; dropping events.",
+            count = %self.count,
+            policy = %self.policy,
+            rate_limit_secs = 10,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("events_dropped_total", self.count as u64, "policy" => self.policy);
+    }
+}
@@ -0,0 +1,48 @@
+use super::InternalEvent;
+use metrics::counter;
+
+#[derive(Debug)]
+pub struct FifoReaderConnected<'a> {
+    pub path: &'a std::path::Path,
+}
+
+impl InternalEvent for FifoReaderConnected<'_> {
+    fn emit_logs(&self) {
+        debug!(message = "Opened named pipe for writing.", path = ?self.path);
+    }
+
+    fn emit_metrics(&self) {
+        counter!("connections_established_total", 1, "mode" => "fifo");
+    }
+}
+
+/// Frames that never reached a reader of the pipe, either because none
+/// had it open or because the buffer kept for the next one overflowed.
+#[derive(Debug)]
+pub struct FifoFramesDropped<'a> {
+    pub path: &'a std::path::Path,
+    pub count: usize,
+    pub byte_size: usize,
+    pub reason: &'static str,
+}
+
+impl InternalEvent for FifoFramesDropped<'_> {
+    fn emit_logs(&self) {
+        warn!(
+            message = "Dropping frames not read from the named pipe.",
+            path = ?self.path,
+            count = self.count,
+            byte_size = self.byte_size,
+            reason = self.reason,
+            rate_limit_secs = 30,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!(
+            "events_dropped_total", self.count as u64,
+            "mode" => "fifo",
+            "reason" => self.reason,
+        );
+    }
+}
@@ -1,4 +1,7 @@
-use std::borrow::Cow;
+use std::{
+    borrow::Cow,
+    sync::atomic::{AtomicU64, Ordering},
+};
 
 mod add_fields;
 mod add_tags;
@@ -13,6 +16,7 @@ mod aws_cloudwatch_logs_subscription_parser;
 #[cfg(feature = "sources-aws_kinesis_firehose")]
 mod aws_kinesis_firehose;
 mod aws_kinesis_streams;
+mod batch;
 mod blackhole;
 #[cfg(feature = "transforms-coercer")]
 mod coercer;
@@ -25,6 +29,8 @@ mod dedupe;
 #[cfg(feature = "sources-docker")]
 mod docker;
 mod elasticsearch;
+#[cfg(all(unix, feature = "sinks-statsd"))]
+mod fifo;
 #[cfg(feature = "sources-generator")]
 mod generator;
 #[cfg(feature = "transforms-grok_parser")]
@@ -110,6 +116,7 @@ pub(crate) use self::aws_cloudwatch_logs_subscription_parser::*;
 #[cfg(feature = "sources-aws_kinesis_firehose")]
 pub use self::aws_kinesis_firehose::*;
 pub use self::aws_kinesis_streams::*;
+pub use self::batch::*;
 pub use self::blackhole::*;
 #[cfg(feature = "transforms-coercer")]
 pub(crate) use self::coercer::*;
@@ -122,6 +129,8 @@ pub(crate) use self::dedupe::*;
 #[cfg(feature = "sources-docker")]
 pub use self::docker::*;
 pub use self::elasticsearch::*;
+#[cfg(all(unix, feature = "sinks-statsd"))]
+pub use self::fifo::*;
 #[cfg(any(feature = "sources-file", feature = "sources-kubernetes-logs"))]
 pub use self::file::*;
 #[cfg(feature = "sources-generator")]
@@ -197,6 +206,47 @@ pub trait InternalEvent {
     fn emit_metrics(&self) {}
 }
 
+static SAMPLE_RATE: AtomicU64 = AtomicU64::new(1);
+
+/// Sets how many occurrences of a high-frequency event each emission of its
+/// metrics stands for. Events that aren't sampled are unaffected.
+pub fn set_sample_rate(rate: u64) {
+    SAMPLE_RATE.store(rate.max(1), Ordering::Relaxed);
+}
+
+/// Counts the occurrences of a high-frequency event to pick the ones whose
+/// metrics are emitted.
+#[derive(Debug)]
+pub(crate) struct Sampler {
+    count: AtomicU64,
+}
+
+impl Sampler {
+    pub(crate) const fn new() -> Self {
+        Self {
+            count: AtomicU64::new(0),
+        }
+    }
+
+    /// The factor to scale this occurrence's metrics by, or `None` when
+    /// they're skipped.
+    pub(crate) fn sample(&self) -> Option<u64> {
+        self.sample_at(SAMPLE_RATE.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn sample_at(&self, rate: u64) -> Option<u64> {
+        if rate <= 1 {
+            return Some(1);
+        }
+        let count = self.count.fetch_add(1, Ordering::Relaxed) + 1;
+        if count % rate == 0 {
+            Some(rate)
+        } else {
+            None
+        }
+    }
+}
+
 pub fn emit(event: impl InternalEvent) {
     event.emit_logs();
     event.emit_metrics();
@@ -1,6 +1,6 @@
 use super::InternalEvent;
 use crate::sources::prometheus::parser::ParserError;
-use metrics::{counter, histogram};
+use metrics::{counter, gauge, histogram};
 use std::borrow::Cow;
 use std::time::Instant;
 
@@ -21,6 +21,34 @@ impl InternalEvent for PrometheusEventReceived {
     }
 }
 
+#[derive(Debug)]
+pub struct PrometheusMetricsFiltered {
+    pub count: usize,
+}
+
+impl InternalEvent for PrometheusMetricsFiltered {
+    fn emit_logs(&self) {
+        trace!(message = "Filtered out scraped metrics.", ?self.count);
+    }
+
+    fn emit_metrics(&self) {
+        counter!("metrics_filtered_total", self.count as u64);
+    }
+}
+
+/// The number of scrapes due but waiting for one of the
+/// `max_concurrent_scrapes` slots.
+#[derive(Debug)]
+pub struct PrometheusScrapeQueueDepth {
+    pub depth: usize,
+}
+
+impl InternalEvent for PrometheusScrapeQueueDepth {
+    fn emit_metrics(&self) {
+        gauge!("scrape_queue_depth", self.depth as f64);
+    }
+}
+
 #[derive(Debug)]
 pub struct PrometheusRequestCompleted {
     pub start: Instant,
@@ -76,6 +104,22 @@ impl InternalEvent for PrometheusErrorResponse {
     }
 }
 
+#[derive(Debug)]
+pub struct PrometheusRedirectFollowed {
+    pub from: http::Uri,
+    pub to: http::Uri,
+}
+
+impl InternalEvent for PrometheusRedirectFollowed {
+    fn emit_logs(&self) {
+        debug!(message = "Following redirect.", from = %self.from, to = %self.to);
+    }
+
+    fn emit_metrics(&self) {
+        counter!("redirects_followed_total", 1);
+    }
+}
+
 #[derive(Debug)]
 pub struct PrometheusHttpError {
     pub error: hyper::Error,
@@ -1,4 +1,4 @@
-use super::InternalEvent;
+use super::{InternalEvent, Sampler};
 use metrics::counter;
 
 #[derive(Debug)]
@@ -16,6 +16,17 @@ impl SocketMode {
             Self::Unix => "unix",
         }
     }
+
+    fn received(&self) -> &'static Sampler {
+        static TCP: Sampler = Sampler::new();
+        static UDP: Sampler = Sampler::new();
+        static UNIX: Sampler = Sampler::new();
+        match self {
+            Self::Tcp => &TCP,
+            Self::Udp => &UDP,
+            Self::Unix => &UNIX,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -30,8 +41,18 @@ impl InternalEvent for SocketEventReceived {
     }
 
     fn emit_metrics(&self) {
-        counter!("events_processed_total", 1, "mode" => self.mode.as_str());
-        counter!("processed_bytes_total", self.byte_size as u64, "mode" => self.mode.as_str());
+        if let Some(scale) = self.mode.received().sample() {
+            self.emit_scaled(scale);
+        }
+    }
+}
+
+impl SocketEventReceived {
+    /// Emits the metrics of `scale` events like this one, of which this is
+    /// the one sampled.
+    fn emit_scaled(&self, scale: u64) {
+        counter!("events_processed_total", scale, "mode" => self.mode.as_str());
+        counter!("processed_bytes_total", self.byte_size as u64 * scale, "mode" => self.mode.as_str());
     }
 }
 
@@ -50,3 +71,45 @@ impl InternalEvent for SocketReceiveError {
         counter!("connection_errors_total", 1, "mode" => self.mode.as_str());
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn events_processed(mode: &str) -> f64 {
+        let controller = crate::metrics::get_controller().unwrap();
+        crate::metrics::capture_metrics(controller)
+            .map(crate::Event::into_metric)
+            .filter(|metric| {
+                metric.name == "events_processed_total"
+                    && metric.tags.as_ref().and_then(|tags| tags.get("mode"))
+                        == Some(&mode.to_owned())
+            })
+            .map(|metric| match metric.value {
+                crate::event::metric::MetricValue::Counter { value } => value,
+                _ => 0.0,
+            })
+            .sum()
+    }
+
+    #[test]
+    fn scales_sampled_events() {
+        let _ = crate::metrics::init();
+        let sampler = Sampler::new();
+        let event = SocketEventReceived {
+            mode: SocketMode::Unix,
+            byte_size: 10,
+        };
+
+        for _ in 0..3 {
+            let before = events_processed("unix");
+            for _ in 0..4 {
+                if let Some(scale) = sampler.sample_at(4) {
+                    event.emit_scaled(scale);
+                }
+            }
+            // Other tests may record metrics concurrently.
+            assert!(events_processed("unix") >= before + 4.0);
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use super::InternalEvent;
 use crate::event::metric::{MetricKind, MetricValue};
-use metrics::counter;
+use metrics::{counter, gauge, histogram};
+use std::{path::Path, time::Duration};
 
 #[derive(Debug)]
 pub struct StatsdInvalidMetricReceived<'a> {
@@ -20,5 +21,254 @@ impl<'a> InternalEvent for StatsdInvalidMetricReceived<'a> {
 
     fn emit_metrics(&self) {
         counter!("processing_errors_total", 1, "error_type" => "invalid_metric");
+        counter!("encode_errors_total", 1,
+            "component_kind" => "sink",
+            "component_type" => "statsd",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct StatsdEmptyMetricName<'a> {
+    pub value: &'a MetricValue,
+}
+
+impl<'a> InternalEvent for StatsdEmptyMetricName<'a> {
+    fn emit_logs(&self) {
+        warn!(
+            message = "Metric has an empty name; dropping event.",
+            value = ?self.value,
+            rate_limit_secs = 30,
+        )
+    }
+
+    fn emit_metrics(&self) {
+        counter!("processing_errors_total", 1, "error_type" => "empty_name");
+        counter!("encode_errors_total", 1,
+            "component_kind" => "sink",
+            "component_type" => "statsd",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct StatsdInvalidValue<'a> {
+    pub name: &'a str,
+    pub value: f64,
+}
+
+impl<'a> InternalEvent for StatsdInvalidValue<'a> {
+    fn emit_logs(&self) {
+        warn!(
+            message = "Metric value is not finite; dropping event.",
+            name = self.name,
+            value = %self.value,
+            rate_limit_secs = 30,
+        )
+    }
+
+    fn emit_metrics(&self) {
+        counter!("processing_errors_total", 1, "error_type" => "invalid_value");
+    }
+}
+
+#[derive(Debug)]
+pub struct StatsdMetricEncoded {
+    pub metric_type: &'static str,
+}
+
+impl InternalEvent for StatsdMetricEncoded {
+    fn emit_metrics(&self) {
+        counter!("metrics_encoded_total", 1, "metric_type" => self.metric_type);
+    }
+}
+
+#[derive(Debug)]
+pub struct StatsdZeroCounterSuppressed<'a> {
+    pub name: &'a str,
+}
+
+impl<'a> InternalEvent for StatsdZeroCounterSuppressed<'a> {
+    fn emit_logs(&self) {
+        trace!(
+            message = "Suppressed zero-valued counter.",
+            name = self.name
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("events_suppressed_total", 1, "reason" => "zero_counter");
+    }
+}
+
+#[derive(Debug)]
+pub struct StatsdUnchangedGaugeSuppressed<'a> {
+    pub name: &'a str,
+}
+
+impl<'a> InternalEvent for StatsdUnchangedGaugeSuppressed<'a> {
+    fn emit_logs(&self) {
+        trace!(message = "Suppressed unchanged gauge.", name = self.name);
+    }
+
+    fn emit_metrics(&self) {
+        counter!("events_suppressed_total", 1, "reason" => "unchanged_gauge");
+    }
+}
+
+#[derive(Debug)]
+pub struct StatsdMetricSampledOut<'a> {
+    pub name: &'a str,
+}
+
+impl<'a> InternalEvent for StatsdMetricSampledOut<'a> {
+    fn emit_logs(&self) {
+        trace!(message = "Metric was not sampled.", name = self.name);
+    }
+
+    fn emit_metrics(&self) {
+        counter!("events_suppressed_total", 1, "reason" => "sampled");
+    }
+}
+
+#[derive(Debug)]
+pub struct StatsdBatchSpilled<'a> {
+    pub byte_size: usize,
+    pub path: &'a Path,
+    pub error: crate::Error,
+}
+
+impl<'a> InternalEvent for StatsdBatchSpilled<'a> {
+    fn emit_logs(&self) {
+        warn!(
+            message = "Failed to send batch; spilled it to disk.",
+            byte_size = self.byte_size,
+            path = ?self.path,
+            error = %self.error,
+            rate_limit_secs = 10,
+        )
+    }
+
+    fn emit_metrics(&self) {
+        counter!("spilled_batches_total", 1);
+    }
+}
+
+#[derive(Debug)]
+pub struct StatsdSpillFailed<'a> {
+    pub error: std::io::Error,
+    pub path: &'a Path,
+}
+
+impl<'a> InternalEvent for StatsdSpillFailed<'a> {
+    fn emit_logs(&self) {
+        error!(
+            message = "Unable to access disk fallback file.",
+            path = ?self.path,
+            error = %self.error,
+            rate_limit_secs = 10,
+        )
+    }
+
+    fn emit_metrics(&self) {
+        counter!("spill_errors_total", 1);
+    }
+}
+
+#[derive(Debug)]
+pub struct StatsdSpilledBatchesDropped {
+    pub count: usize,
+    pub byte_size: u64,
+}
+
+impl InternalEvent for StatsdSpilledBatchesDropped {
+    fn emit_logs(&self) {
+        warn!(
+            message = "Disk fallback file is full; dropped the oldest spilled batches.",
+            count = self.count,
+            byte_size = self.byte_size,
+            rate_limit_secs = 10,
+        )
+    }
+
+    fn emit_metrics(&self) {
+        counter!("spilled_batches_dropped_total", self.count as u64);
+    }
+}
+
+#[derive(Debug)]
+pub struct StatsdSpilledBatchesReplayed {
+    pub count: usize,
+}
+
+impl InternalEvent for StatsdSpilledBatchesReplayed {
+    fn emit_logs(&self) {
+        debug!(message = "Replayed spilled batches.", count = self.count);
+    }
+
+    fn emit_metrics(&self) {
+        counter!("replayed_batches_total", self.count as u64);
+    }
+}
+
+#[derive(Debug)]
+pub struct StatsdConnectionOpened {
+    pub mode: &'static str,
+}
+
+impl InternalEvent for StatsdConnectionOpened {
+    fn emit_logs(&self) {
+        debug!(message = "Opened connection.", mode = self.mode);
+    }
+
+    fn emit_metrics(&self) {
+        counter!("connections_opened_total", 1, "mode" => self.mode);
+    }
+}
+
+#[derive(Debug)]
+pub struct StatsdConnectionClosed {
+    pub mode: &'static str,
+}
+
+impl InternalEvent for StatsdConnectionClosed {
+    fn emit_logs(&self) {
+        debug!(message = "Closed connection.", mode = self.mode);
+    }
+
+    fn emit_metrics(&self) {
+        counter!("connections_closed_total", 1, "mode" => self.mode);
+    }
+}
+
+#[derive(Debug)]
+pub struct StatsdBufferedBytes {
+    pub byte_size: usize,
+}
+
+impl InternalEvent for StatsdBufferedBytes {
+    fn emit_metrics(&self) {
+        gauge!("buffer_bytes", self.byte_size as f64);
+    }
+}
+
+#[derive(Debug)]
+pub struct StatsdShutdownDrain {
+    pub pending_events: usize,
+    pub duration: Duration,
+}
+
+impl InternalEvent for StatsdShutdownDrain {
+    fn emit_logs(&self) {
+        debug!(
+            message = "Drained pending events on shutdown.",
+            pending_events = self.pending_events,
+            duration = ?self.duration,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        histogram!("shutdown_drain_duration_nanoseconds", self.duration);
+        gauge!("shutdown_pending_events", self.pending_events as f64);
     }
 }
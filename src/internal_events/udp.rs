@@ -1,5 +1,6 @@
-use super::InternalEvent;
-use metrics::counter;
+use super::{truncate_string_at, InternalEvent};
+use metrics::{counter, histogram};
+use std::{net::SocketAddr, time::Duration};
 
 #[derive(Debug)]
 pub struct UdpSendIncomplete {
@@ -22,3 +23,84 @@ impl InternalEvent for UdpSendIncomplete {
         counter!("connection_send_errors_total", 1, "mode" => "udp");
     }
 }
+
+/// A datagram larger than the configured `max_datagram_size`, which is
+/// still sent but may be fragmented or rejected on the way.
+#[derive(Debug)]
+pub struct UdpOversizedDatagram {
+    pub byte_size: usize,
+    pub max_datagram_size: usize,
+}
+
+impl InternalEvent for UdpOversizedDatagram {
+    fn emit_logs(&self) {
+        warn!(
+            message = "Sending a UDP datagram larger than the maximum datagram size.",
+            byte_size = self.byte_size,
+            max_datagram_size = self.max_datagram_size,
+            rate_limit_secs = 30,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("oversized_datagrams_total", 1, "mode" => "udp");
+    }
+}
+
+#[derive(Debug)]
+pub struct UdpSendFailed<'a> {
+    pub error: &'a std::io::Error,
+}
+
+impl<'a> InternalEvent for UdpSendFailed<'a> {
+    fn emit_logs(&self) {
+        error!(
+            message = "UDP send failed.",
+            error = %self.error,
+            kind = ?self.error.kind(),
+            rate_limit_secs = 30,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("connection_send_errors_total", 1, "mode" => "udp");
+    }
+}
+
+/// The details of a failed send, logged for a sample of the failures
+/// counted by `UdpSendFailed`.
+#[derive(Debug)]
+pub struct UdpSendFailedDetail<'a> {
+    pub address: Option<SocketAddr>,
+    pub error: &'a std::io::Error,
+    pub datagram: &'a [u8],
+}
+
+impl<'a> InternalEvent for UdpSendFailedDetail<'a> {
+    fn emit_logs(&self) {
+        let datagram = String::from_utf8_lossy(self.datagram);
+        error!(
+            message = "UDP send failed.",
+            address = ?self.address,
+            error = %self.error,
+            kind = ?self.error.kind(),
+            byte_size = self.datagram.len(),
+            preview = %truncate_string_at(&datagram, 64),
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct UdpDnsResolved {
+    pub duration: Duration,
+}
+
+impl InternalEvent for UdpDnsResolved {
+    fn emit_logs(&self) {
+        debug!(message = "Resolved DNS.", duration = ?self.duration);
+    }
+
+    fn emit_metrics(&self) {
+        histogram!("dns_resolution_duration_nanoseconds", self.duration);
+    }
+}
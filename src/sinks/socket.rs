@@ -38,7 +38,10 @@ impl SocketSinkConfig {
         tls: Option<TlsConfig>,
     ) -> Self {
         SocketSinkConfig {
-            mode: Mode::Tcp(TcpSinkConfig { address, tls }),
+            mode: Mode::Tcp(TcpSinkConfig {
+                tls,
+                ..TcpSinkConfig::new(address)
+            }),
             encoding,
         }
     }
@@ -96,9 +99,7 @@ mod test {
         let receiver = UdpSocket::bind(addr).unwrap();
 
         let config = SocketSinkConfig {
-            mode: Mode::Udp(UdpSinkConfig {
-                address: addr.to_string(),
-            }),
+            mode: Mode::Udp(UdpSinkConfig::new(addr.to_string())),
             encoding: Encoding::Json.into(),
         };
         let context = SinkContext::new_test();
@@ -140,10 +141,7 @@ mod test {
 
         let addr = next_addr();
         let config = SocketSinkConfig {
-            mode: Mode::Tcp(TcpSinkConfig {
-                address: addr.to_string(),
-                tls: None,
-            }),
+            mode: Mode::Tcp(TcpSinkConfig::new(addr.to_string())),
             encoding: Encoding::Json.into(),
         };
 
@@ -202,7 +200,6 @@ mod test {
         let addr = next_addr();
         let config = SocketSinkConfig {
             mode: Mode::Tcp(TcpSinkConfig {
-                address: addr.to_string(),
                 tls: Some(TlsConfig {
                     enabled: Some(true),
                     options: TlsOptions {
@@ -212,6 +209,7 @@ mod test {
                         ..Default::default()
                     },
                 }),
+                ..TcpSinkConfig::new(addr.to_string())
             }),
             encoding: Encoding::Text.into(),
         };
@@ -316,10 +314,7 @@ mod test {
 
         let addr = next_addr();
         let config = SocketSinkConfig {
-            mode: Mode::Tcp(TcpSinkConfig {
-                address: addr.to_string(),
-                tls: None,
-            }),
+            mode: Mode::Tcp(TcpSinkConfig::new(addr.to_string())),
             encoding: Encoding::Text.into(),
         };
 
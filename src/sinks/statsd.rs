@@ -1,28 +1,119 @@
 #[cfg(unix)]
-use crate::sinks::util::unix::{UnixService, UnixSinkConfig};
+use crate::sinks::util::{
+    fifo::{FifoService, FifoSinkConfig},
+    unix::{UnixService, UnixSinkConfig},
+};
 use crate::{
     config::{DataType, GenerateConfig, SinkConfig, SinkContext, SinkDescription},
     event::metric::{Metric, MetricKind, MetricValue, StatisticKind},
     event::Event,
-    internal_events::StatsdInvalidMetricReceived,
-    sinks::util::{encode_namespace, BatchConfig, BatchSettings, BatchSink, Buffer, Compression},
+    internal_events::{
+        StatsdBatchSpilled, StatsdBufferedBytes, StatsdConnectionClosed, StatsdConnectionOpened,
+        StatsdEmptyMetricName, StatsdInvalidMetricReceived, StatsdInvalidValue,
+        StatsdMetricEncoded, StatsdMetricSampledOut, StatsdShutdownDrain, StatsdSpillFailed,
+        StatsdSpilledBatchesDropped, StatsdSpilledBatchesReplayed, StatsdUnchangedGaugeSuppressed,
+        StatsdZeroCounterSuppressed,
+    },
     sinks::util::{
-        tcp::{TcpService, TcpSinkConfig},
+        tcp::{ConnectionMode, TcpService, TcpSinkConfig},
         udp::{UdpService, UdpSinkConfig},
     },
+    sinks::util::{
+        BatchConfig, BatchSettings, BatchSink, Buffer, Compression, ConnectionEvent,
+        ConnectionHook, Dropped, OverflowPolicy,
+    },
 };
 use futures::{future, FutureExt, TryFutureExt};
-use futures01::{stream, Sink};
+use futures01::{stream, try_ready, Async, Poll as Poll01, Sink, StartSend};
+use rand::random;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
 
-use std::collections::BTreeMap;
-use std::fmt::Display;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Display, Write as _};
+use std::io;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Weak,
+};
 use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::{
+    fs::{File, OpenOptions},
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader},
+    sync::Mutex,
+    time::{delay_for, Delay},
+};
 use tower::{Service, ServiceBuilder};
 
+// The smallest metric line that can be sent, e.g. `a:1|c\n`.
+const MIN_BATCH_BYTES: usize = 6;
+
+// A representative metric line with a few tags, e.g.
+// `http.requests:1|c|#host:web-01,method:get,status:200\n`.
+const TYPICAL_METRIC_BYTES: usize = 64;
+
+#[derive(Debug, Snafu)]
+enum BuildError {
+    #[snafu(display("Batch max_bytes must be at least {} bytes", min))]
+    BatchBytesTooSmall { min: usize },
+    #[snafu(display("Batch max_events must be greater than zero"))]
+    BatchEventsZero,
+    #[snafu(display("Batch timeout_secs must be greater than zero"))]
+    BatchTimeoutZero,
+    #[snafu(display("soft_max_bytes must be between 1 and the batch max_bytes ({})", max))]
+    SoftMaxBytesOutOfRange { max: usize },
+    #[snafu(display("max_packets_per_flush must be greater than zero"))]
+    MaxPacketsPerFlushZero,
+    #[snafu(display("Sample rates must be greater than 0 and at most 1"))]
+    SampleRateOutOfRange,
+    #[snafu(display("Invalid name rewrite pattern: {}", source))]
+    InvalidNameRewrite { source: regex::Error },
+}
+
 pub struct StatsdSvc {
     client: Client,
+    fallback: Option<Arc<DiskFallback>>,
+    buffered: Option<BufferedBytes>,
+    /// When set, the lines of each frame are sorted by the metric name
+    /// preceding this value delimiter.
+    sort_by_name: Option<char>,
+    /// Whether the lines of each frame are sorted by metric type.
+    sort_by_type: bool,
+    /// When set, limits how often frames are sent.
+    pacing: Option<Pacing>,
+}
+
+/// Spaces packets at least `interval` apart. Batches that fill up in the
+/// meantime wait in the sink, so their metrics go out with later packets.
+struct Pacing {
+    interval: Duration,
+    delay: Option<Delay>,
+}
+
+impl Pacing {
+    fn new(flush_interval: Duration, max_packets: usize) -> Self {
+        Self {
+            interval: flush_interval.div_f64(max_packets as f64),
+            delay: None,
+        }
+    }
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if let Some(delay) = &mut self.delay {
+            futures::ready!(delay.poll_unpin(cx));
+            self.delay = None;
+        }
+        Poll::Ready(())
+    }
+
+    fn sent(&mut self) {
+        self.delay = Some(delay_for(self.interval));
+    }
 }
 
 enum Client {
@@ -30,6 +121,10 @@ enum Client {
     Udp(UdpService),
     #[cfg(unix)]
     Unix(UnixService),
+    #[cfg(unix)]
+    Fifo(FifoService),
+    #[cfg(test)]
+    Memory(MemoryConfig),
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -37,25 +132,288 @@ enum Client {
 // #[serde(deny_unknown_fields)]
 pub struct StatsdSinkConfig {
     pub namespace: Option<String>,
+    /// A tag holding a per-metric namespace, which overrides `namespace`.
+    /// The tag itself is not sent.
+    pub namespace_tag: Option<String>,
+    /// Joins the namespace and the metric name.
+    #[serde(default = "default_namespace_separator")]
+    pub namespace_separator: String,
+    /// With the DogStatsD dialect, a tag holding the id of the entity, such
+    /// as a container, that sent the metric. It's sent as the entity id
+    /// extension instead of a tag.
+    pub entity_id_tag: Option<String>,
     #[serde(flatten)]
     pub mode: Mode,
     #[serde(default)]
     pub batch: BatchConfig,
+    /// Whether each metric is sent as soon as it's encoded, in a packet of
+    /// its own, rather than batched.
+    #[serde(default)]
+    pub batch_disabled: bool,
+    #[serde(default)]
+    pub overflow_policy: OverflowPolicy,
+    /// Whether batches are flushed on wall-clock multiples of
+    /// `batch.timeout_secs`, rather than that long after their first event.
+    #[serde(default)]
+    pub align_batch_timeout: bool,
+    /// Whether the batch timeout shrinks as the batch fills up.
+    #[serde(default)]
+    pub adaptive_timeout: bool,
+    /// Flushes a partial batch once this many milliseconds pass without a
+    /// new event, ahead of `batch.timeout_secs`.
+    pub idle_flush_ms: Option<u64>,
+    /// Flushes a batch once it holds this many bytes, below `batch.max_bytes`.
+    pub soft_max_bytes: Option<usize>,
+    /// When set, at most this many packets are sent per `batch.timeout_secs`,
+    /// spaced evenly over it, rather than in a burst.
+    pub max_packets_per_flush: Option<usize>,
+    #[serde(default)]
+    pub dialect: Dialect,
+    pub disk_fallback_path: Option<PathBuf>,
+    /// The most the disk fallback file may hold. The oldest batches are
+    /// dropped to make room for new ones.
+    #[serde(default = "default_disk_fallback_max_bytes")]
+    pub disk_fallback_max_bytes: u64,
+    #[serde(default = "default_value_delimiter")]
+    pub value_delimiter: char,
+    /// Introduces the tag block of each line.
+    #[serde(default = "default_tag_prefix")]
+    pub tag_prefix: String,
+    #[serde(default)]
+    pub default_sample_rates: DefaultSampleRates,
+    /// When set, only this fraction of counters and distributions is sent,
+    /// chosen at random, with the rate for the server to scale them back up.
+    pub sample_rate: Option<f64>,
+    /// The name of the gauge sent for each quantile of a summary, where
+    /// `{name}` is the summary's name and `{quantile}` the percentile.
+    #[serde(default = "default_summary_quantile_name")]
+    pub summary_quantile_name: String,
+    /// Whether counters with a value of zero are dropped instead of sent.
+    #[serde(default)]
+    pub suppress_zero_counters: bool,
+    /// Whether absolute counters, histograms and summaries are sent as what
+    /// was added to them since the previous value of the same series. The
+    /// first value of each series then only sets the baseline.
+    #[serde(default)]
+    pub absolute_to_incremental: bool,
+    /// When set, absolute gauges whose value hasn't changed since it was
+    /// last sent for the same series are dropped, until this many seconds
+    /// have passed since it was sent.
+    pub unchanged_gauge_interval_secs: Option<u64>,
+    #[serde(default)]
+    pub set_emission: SetEmission,
+    /// Rewrites applied to metric names, in order, before namespacing.
+    #[serde(default)]
+    pub name_rewrites: Vec<NameRewrite>,
+    /// The name given to metrics without one, which are dropped when not
+    /// set.
+    pub empty_name_placeholder: Option<String>,
+    /// Whether the lines of each packet are sorted by metric name, rather
+    /// than sent in arrival order.
+    #[serde(default)]
+    pub sort_by_name: bool,
+    /// Whether the lines of each packet are ordered by metric type:
+    /// counters, then gauges, then distributions, then sets.
+    #[serde(default)]
+    pub sort_by_type: bool,
+    /// Used instead of `mode` when it can't be connected to at startup.
+    pub fallback: Option<Box<Mode>>,
+}
+
+/// The flavor of the StatsD protocol spoken by the receiver.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Dialect {
+    Statsd,
+    Dogstatsd,
+}
+
+impl Default for Dialect {
+    fn default() -> Self {
+        Dialect::Statsd
+    }
+}
+
+/// Replaces every match of `pattern` in metric names with `replacement`,
+/// which may refer to capture groups like `$1`.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct NameRewrite {
+    pub pattern: String,
+    pub replacement: String,
+}
+
+/// How sets are sent.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SetEmission {
+    /// A set line for each member.
+    Members,
+    /// A single gauge holding the number of members.
+    Count,
+}
+
+impl Default for SetEmission {
+    fn default() -> Self {
+        SetEmission::Members
+    }
+}
+
+/// Sample rates applied to metrics that don't carry their own. Counters
+/// are scaled down by their rate, so the receiver scales them back up.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct DefaultSampleRates {
+    pub counter: Option<f64>,
+    pub distribution: Option<f64>,
+}
+
+/// The options of the TCP mode: those of the `socket` sink, and the ones
+/// only its batched `TcpService` supports.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct StatsdTcpConfig {
+    #[serde(flatten)]
+    pub socket: TcpSinkConfig,
+    /// Whether batches share one connection or each get their own.
+    #[serde(default)]
+    pub connection_mode: ConnectionMode,
+    /// In `per_batch` mode, when set, the connection is kept open between
+    /// batches and closed after this many seconds without a send.
+    pub idle_timeout_secs: Option<u64>,
+    /// Whether a batch is written to the connection as a single buffer, or
+    /// line by line.
+    #[serde(default = "crate::serde::default_true")]
+    pub write_coalescing: bool,
+    /// Bounds each connection attempt, including DNS resolution and the
+    /// TLS handshake.
+    pub connect_timeout_secs: Option<u64>,
+}
+
+impl StatsdTcpConfig {
+    pub fn new(address: String) -> Self {
+        Self {
+            socket: TcpSinkConfig::new(address),
+            connection_mode: ConnectionMode::default(),
+            idle_timeout_secs: None,
+            write_coalescing: true,
+            connect_timeout_secs: None,
+        }
+    }
+
+    fn build_service(&self, cx: SinkContext) -> crate::Result<(TcpService, super::Healthcheck)> {
+        let (service, healthcheck) = self.socket.build_service(cx)?;
+        let service = service
+            .with_connection_mode(self.connection_mode)
+            .with_idle_timeout(self.idle_timeout_secs.map(Duration::from_secs))
+            .with_write_coalescing(self.write_coalescing)
+            .with_connect_timeout(self.connect_timeout_secs.map(Duration::from_secs));
+        Ok((service, healthcheck))
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(tag = "mode", rename_all = "snake_case")]
 pub enum Mode {
-    Tcp(TcpSinkConfig),
+    Tcp(StatsdTcpConfig),
     Udp(UdpSinkConfig),
     #[cfg(unix)]
     Unix(UnixSinkConfig),
+    /// Writes to a named pipe.
+    #[cfg(unix)]
+    Fifo(FifoSinkConfig),
+    /// Keeps the encoded frames in memory. Only available in tests.
+    #[cfg(test)]
+    Memory(MemoryConfig),
+}
+
+impl Mode {
+    fn name(&self) -> &'static str {
+        match self {
+            Mode::Tcp(_) => "tcp",
+            Mode::Udp(_) => "udp",
+            #[cfg(unix)]
+            Mode::Unix(_) => "unix",
+            #[cfg(unix)]
+            Mode::Fifo(_) => "fifo",
+            #[cfg(test)]
+            Mode::Memory(_) => "memory",
+        }
+    }
+
+    fn target(&self) -> String {
+        match self {
+            Mode::Tcp(config) => config.socket.address.clone(),
+            Mode::Udp(config) => config.address.clone(),
+            #[cfg(unix)]
+            Mode::Unix(config) => config.path.display().to_string(),
+            #[cfg(unix)]
+            Mode::Fifo(config) => config.path.display().to_string(),
+            #[cfg(test)]
+            Mode::Memory(_) => "memory".to_owned(),
+        }
+    }
+}
+
+/// A ring buffer of the most recent `capacity` frames sent by the sink.
+#[cfg(test)]
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct MemoryConfig {
+    pub capacity: usize,
+    #[serde(skip)]
+    frames: Arc<std::sync::Mutex<std::collections::VecDeque<Vec<u8>>>>,
+}
+
+#[cfg(test)]
+impl MemoryConfig {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            frames: Default::default(),
+        }
+    }
+
+    /// The frames sent so far, oldest first.
+    pub fn frames(&self) -> Vec<Vec<u8>> {
+        self.frames.lock().unwrap().iter().cloned().collect()
+    }
+
+    fn push(&self, frame: Vec<u8>) {
+        let mut frames = self.frames.lock().unwrap();
+        frames.push_back(frame);
+        while frames.len() > self.capacity {
+            frames.pop_front();
+        }
+    }
 }
 
 inventory::submit! {
     SinkDescription::new::<StatsdSinkConfig>("statsd")
 }
 
+fn default_disk_fallback_max_bytes() -> u64 {
+    100 * 1024 * 1024
+}
+
+fn default_value_delimiter() -> char {
+    ':'
+}
+
+fn default_tag_prefix() -> String {
+    "#".to_owned()
+}
+
+fn default_namespace_separator() -> String {
+    ".".to_owned()
+}
+
+/// Metrics with this tag are sent without a namespace, as their name is
+/// already fully qualified. The tag itself isn't sent.
+const NO_NAMESPACE_TAG: &str = "__no_namespace";
+
+fn default_summary_quantile_name() -> String {
+    "{name}.q{quantile}".to_owned()
+}
+
 fn default_address() -> SocketAddr {
     SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8125)
 }
@@ -64,10 +422,35 @@ impl GenerateConfig for StatsdSinkConfig {
     fn generate_config() -> toml::Value {
         toml::Value::try_from(&Self {
             namespace: None,
+            namespace_tag: None,
+            namespace_separator: default_namespace_separator(),
+            entity_id_tag: None,
             batch: Default::default(),
-            mode: Mode::Udp(UdpSinkConfig {
-                address: default_address().to_string(),
-            }),
+            batch_disabled: false,
+            overflow_policy: Default::default(),
+            align_batch_timeout: false,
+            adaptive_timeout: false,
+            idle_flush_ms: None,
+            soft_max_bytes: None,
+            max_packets_per_flush: None,
+            dialect: Default::default(),
+            disk_fallback_path: None,
+            disk_fallback_max_bytes: default_disk_fallback_max_bytes(),
+            value_delimiter: default_value_delimiter(),
+            tag_prefix: default_tag_prefix(),
+            default_sample_rates: Default::default(),
+            sample_rate: None,
+            summary_quantile_name: default_summary_quantile_name(),
+            suppress_zero_counters: false,
+            absolute_to_incremental: false,
+            unchanged_gauge_interval_secs: None,
+            set_emission: SetEmission::Members,
+            name_rewrites: Vec::new(),
+            empty_name_placeholder: None,
+            sort_by_name: false,
+            sort_by_type: false,
+            fallback: None,
+            mode: Mode::Udp(UdpSinkConfig::new(default_address().to_string())),
         })
         .unwrap()
     }
@@ -85,38 +468,128 @@ impl SinkConfig for StatsdSinkConfig {
         // However we need to leave some space for +1 extra trailing event in the buffer.
         // Also one might keep an eye on server side limitations, like
         // mentioned here https://github.com/DataDog/dd-agent/issues/2638
-        let batch = BatchSettings::default()
+        let mut batch = BatchSettings::default()
             .bytes(1300)
             .events(1000)
             .timeout(1)
             .parse_config(self.batch)?;
-        let namespace = self.namespace.clone();
+        if self.batch_disabled {
+            // A batch of one is full as soon as it's started, so it's sent
+            // right away.
+            batch.size.events = 1;
+        }
+        validate_batch(&batch, self.soft_max_bytes)?;
+        if self.max_packets_per_flush == Some(0) {
+            return Err(BuildError::MaxPacketsPerFlushZero.into());
+        }
+        warn_if_batch_too_small(batch.size.bytes, self.namespace.as_deref());
+        validate_sample_rates(&self.default_sample_rates, self.sample_rate)?;
+        let options = EncodingOptions {
+            namespace: self.namespace.clone(),
+            namespace_tag: self.namespace_tag.clone(),
+            namespace_separator: self.namespace_separator.clone(),
+            entity_id_tag: self.entity_id_tag.clone(),
+            dialect: self.dialect,
+            value_delimiter: self.value_delimiter,
+            tag_prefix: self.tag_prefix.clone(),
+            default_sample_rates: self.default_sample_rates,
+            sample_rate: self.sample_rate,
+            summary_quantile_name: self.summary_quantile_name.clone(),
+            suppress_zero_counters: self.suppress_zero_counters,
+            set_emission: self.set_emission,
+            name_rewrites: compile_name_rewrites(&self.name_rewrites)?,
+            empty_name_placeholder: self.empty_name_placeholder.clone(),
+        };
 
-        let (client, healthcheck) = match &self.mode {
-            Mode::Tcp(config) => {
-                let (service, healthcheck) = config.build_service(cx.clone())?;
-                (Client::Tcp(service), healthcheck)
-            }
-            Mode::Udp(config) => {
-                let (service, healthcheck) = config.build_service(cx.clone())?;
-                (Client::Udp(service), healthcheck)
-            }
-            #[cfg(unix)]
-            Mode::Unix(config) => {
-                let (service, healthcheck) = config.build_service()?;
-                (Client::Unix(service), healthcheck)
+        let mode = self.select_mode(cx.clone()).await?;
+        let (client, healthcheck) = build_client(mode, cx.clone())?;
+        info!(
+            message = "Sending metrics.",
+            mode = %mode.name(),
+            target = %mode.target(),
+        );
+
+        let fallback = match &self.disk_fallback_path {
+            Some(path) => {
+                let fallback = Arc::new(DiskFallback::new(
+                    path.clone(),
+                    self.disk_fallback_max_bytes,
+                ));
+                let (client, _) = build_client(mode, cx.clone())?;
+                let replay_service = StatsdSvc {
+                    client,
+                    fallback: None,
+                    buffered: None,
+                    sort_by_name: None,
+                    sort_by_type: false,
+                    pacing: None,
+                };
+                tokio::spawn(replay_spilled(
+                    Arc::downgrade(&fallback),
+                    replay_service,
+                    batch.timeout,
+                ));
+                Some(fallback)
             }
+            None => None,
         };
-        let service = StatsdSvc { client };
+        let buffered = BufferedBytes::default();
+        let service = StatsdSvc {
+            client,
+            fallback,
+            buffered: Some(buffered.clone()),
+            sort_by_name: if self.sort_by_name {
+                Some(self.value_delimiter)
+            } else {
+                None
+            },
+            sort_by_type: self.sort_by_type,
+            pacing: self
+                .max_packets_per_flush
+                .map(|max_packets| Pacing::new(batch.timeout, max_packets)),
+        };
+
+        let mut buffer = Buffer::new(batch.size, Compression::None);
+        if let Some(bytes) = self.soft_max_bytes {
+            buffer = buffer.with_soft_max_bytes(bytes);
+        }
 
         let sink = BatchSink::new(
             ServiceBuilder::new().service(service),
-            Buffer::new(batch.size, Compression::None),
+            buffer,
             batch.timeout,
             cx.acker(),
         )
-        .sink_map_err(|e| error!("Fatal statsd sink error: {}", e))
-        .with_flat_map(move |event| stream::iter_ok(encode_event(event, namespace.as_deref())));
+        .with_overflow_policy(self.overflow_policy)
+        .with_drop_hook(buffered.drop_hook())
+        .with_aligned_timeout(self.align_batch_timeout)
+        .with_adaptive_timeout(self.adaptive_timeout)
+        .with_idle_timeout(self.idle_flush_ms.map(Duration::from_millis));
+        let mut deltas = if self.absolute_to_incremental {
+            Some(AbsoluteDeltas::new(DELTA_SERIES_TTL))
+        } else {
+            None
+        };
+        let mut unchanged_gauges = self
+            .unchanged_gauge_interval_secs
+            .map(|secs| UnchangedGauges::new(Duration::from_secs(secs)));
+        let sink = ShutdownDrain::new(sink)
+            .sink_map_err(|e| error!("Fatal statsd sink error: {}", e))
+            .with_flat_map(move |event| {
+                let encoded = match &mut deltas {
+                    Some(deltas) => deltas.to_incremental(event),
+                    None => Some(event),
+                }
+                .and_then(|event| match &mut unchanged_gauges {
+                    Some(unchanged_gauges) => unchanged_gauges.filter(event),
+                    None => Some(event),
+                })
+                .and_then(|event| encode_event(event, &options));
+                if let Some(bytes) = &encoded {
+                    buffered.add(bytes.len());
+                }
+                stream::iter_ok(encoded)
+            });
 
         Ok((
             super::VectorSink::Futures01Sink(Box::new(sink)),
@@ -133,12 +606,133 @@ impl SinkConfig for StatsdSinkConfig {
     }
 }
 
-fn encode_tags(tags: &BTreeMap<String, String>) -> String {
+impl StatsdSinkConfig {
+    /// Picks the configured mode, or the fallback mode if the configured
+    /// one can't be connected to.
+    async fn select_mode(&self, cx: SinkContext) -> crate::Result<&Mode> {
+        let fallback = match &self.fallback {
+            Some(fallback) => fallback,
+            None => return Ok(&self.mode),
+        };
+
+        let (_, healthcheck) = build_client(&self.mode, cx)?;
+        match healthcheck.await {
+            Ok(()) => Ok(&self.mode),
+            Err(error) => {
+                warn!(
+                    message = "Unable to connect; using fallback mode.",
+                    mode = %self.mode.name(),
+                    fallback = %fallback.name(),
+                    %error,
+                );
+                Ok(fallback)
+            }
+        }
+    }
+}
+
+fn build_client(mode: &Mode, cx: SinkContext) -> crate::Result<(Client, super::Healthcheck)> {
+    match mode {
+        Mode::Tcp(config) => {
+            let (service, healthcheck) = config.build_service(cx)?;
+            let service = service.with_connection_hook(connection_hook(mode.name()));
+            Ok((Client::Tcp(service), healthcheck))
+        }
+        Mode::Udp(config) => {
+            let (service, healthcheck) = config.build_service(cx)?;
+            Ok((Client::Udp(service), healthcheck))
+        }
+        #[cfg(unix)]
+        Mode::Unix(config) => {
+            let (service, healthcheck) = config.build_service()?;
+            let service = service.with_connection_hook(connection_hook(mode.name()));
+            Ok((Client::Unix(service), healthcheck))
+        }
+        #[cfg(unix)]
+        Mode::Fifo(config) => {
+            let (service, healthcheck) = config.build_service()?;
+            Ok((Client::Fifo(service), healthcheck))
+        }
+        #[cfg(test)]
+        Mode::Memory(config) => Ok((Client::Memory(config.clone()), future::ok(()).boxed())),
+    }
+}
+
+fn connection_hook(mode: &'static str) -> ConnectionHook {
+    Arc::new(move |event| match event {
+        ConnectionEvent::Opened => emit!(StatsdConnectionOpened { mode }),
+        ConnectionEvent::Closed => emit!(StatsdConnectionClosed { mode }),
+    })
+}
+
+fn validate_batch<B>(
+    batch: &BatchSettings<B>,
+    soft_max_bytes: Option<usize>,
+) -> Result<(), BuildError> {
+    if batch.size.bytes < MIN_BATCH_BYTES {
+        return Err(BuildError::BatchBytesTooSmall {
+            min: MIN_BATCH_BYTES,
+        });
+    }
+    if batch.size.events == 0 {
+        return Err(BuildError::BatchEventsZero);
+    }
+    if batch.timeout.as_secs() == 0 {
+        return Err(BuildError::BatchTimeoutZero);
+    }
+    match soft_max_bytes {
+        Some(bytes) if bytes == 0 || bytes > batch.size.bytes => {
+            Err(BuildError::SoftMaxBytesOutOfRange {
+                max: batch.size.bytes,
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Warns when metrics of a typical size, with the namespace prepended, are
+/// larger than a batch and so will be dropped.
+fn warn_if_batch_too_small(max_bytes: usize, namespace: Option<&str>) {
+    let needed = TYPICAL_METRIC_BYTES + namespace.map_or(0, |namespace| namespace.len() + 1);
+    if max_bytes < needed {
+        warn!(
+            message = "Batch max_bytes is too small to hold a typical metric; larger metrics will be dropped.",
+            max_bytes,
+            needed,
+        );
+    }
+}
+
+fn compile_name_rewrites(rewrites: &[NameRewrite]) -> Result<Vec<(Regex, String)>, BuildError> {
+    rewrites
+        .iter()
+        .map(|rewrite| {
+            let pattern = Regex::new(&rewrite.pattern).context(InvalidNameRewrite)?;
+            Ok((pattern, rewrite.replacement.clone()))
+        })
+        .collect()
+}
+
+fn validate_sample_rates(
+    rates: &DefaultSampleRates,
+    sample_rate: Option<f64>,
+) -> Result<(), BuildError> {
+    let valid = |rate: Option<f64>| rate.map_or(true, |rate| rate > 0.0 && rate <= 1.0);
+    if valid(rates.counter) && valid(rates.distribution) && valid(sample_rate) {
+        Ok(())
+    } else {
+        Err(BuildError::SampleRateOutOfRange)
+    }
+}
+
+fn encode_tags(tags: &BTreeMap<String, String>, dialect: Dialect) -> String {
     let mut parts: Vec<_> = tags
         .iter()
         .map(|(name, value)| {
             if value == "true" {
                 name.to_string()
+            } else if dialect == Dialect::Dogstatsd {
+                format!("{}:{}", name, encode_dogstatsd_tag_value(value))
             } else {
                 format!("{}:{}", name, value)
             }
@@ -148,41 +742,294 @@ fn encode_tags(tags: &BTreeMap<String, String>) -> String {
     parts.join(",")
 }
 
+/// Percent-encodes the characters of a tag value that DogStatsD does not
+/// allow, see https://docs.datadoghq.com/getting_started/tagging/#defining-tags
+fn encode_dogstatsd_tag_value(value: &str) -> Cow<'_, str> {
+    fn is_allowed(byte: u8) -> bool {
+        byte.is_ascii_alphanumeric() || b"_-:./".contains(&byte)
+    }
+
+    if value.bytes().all(is_allowed) {
+        return value.into();
+    }
+
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if is_allowed(byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded.into()
+}
+
+/// Appends the parts for `val` to `line`, separated with a `|` from the
+/// parts already written after the namespace, which ends at `body_start`.
 fn push_event<V: Display>(
-    buf: &mut Vec<String>,
+    line: &mut String,
+    body_start: usize,
     metric: &Metric,
     val: V,
     metric_type: &str,
-    sample_rate: Option<u32>,
+    sample_rate: Option<f64>,
+    entity_id: Option<&str>,
+    options: &EncodingOptions,
 ) {
-    buf.push(format!("{}:{}|{}", metric.name, val, metric_type));
+    if line.len() > body_start {
+        line.push('|');
+    }
+    // Writing to a `String` can't fail.
+    let _ = write!(
+        line,
+        "{}{}{}|{}",
+        metric.name, options.value_delimiter, val, metric_type
+    );
 
     if let Some(sample_rate) = sample_rate {
-        if sample_rate != 1 {
-            buf.push(format!("@{}", 1.0 / f64::from(sample_rate)))
+        if sample_rate < 1.0 {
+            let _ = write!(line, "|@{}", sample_rate);
         }
     };
 
     if let Some(t) = &metric.tags {
-        buf.push(format!("#{}", encode_tags(t)));
+        line.push('|');
+        line.push_str(&options.tag_prefix);
+        line.push_str(&encode_tags(t, options.dialect));
+    };
+
+    if let Some(entity_id) = entity_id {
+        line.push_str("|e:");
+        line.push_str(entity_id);
+    }
+}
+
+/// Estimates the size of `metric` encoded with `options`, adding what the
+/// namespace, entity id and delimiters take beyond the single characters
+/// `Metric::estimated_statsd_size` allows for.
+fn estimated_size(
+    metric: &Metric,
+    namespace: Option<&str>,
+    entity_id: Option<&str>,
+    options: &EncodingOptions,
+) -> usize {
+    let lines = match &metric.value {
+        MetricValue::Set { values } => values.len(),
+        MetricValue::Distribution { values, .. } => values.len(),
+        _ => 1,
     };
+    let per_line = options.value_delimiter.len_utf8() - 1
+        + options.tag_prefix.len().saturating_sub(1)
+        + entity_id.map_or(0, |entity_id| "|e:".len() + entity_id.len());
+    let namespace = namespace.map_or(0, |namespace| {
+        namespace.len() + options.namespace_separator.len()
+    });
+    metric.estimated_statsd_size() + lines * per_line + namespace
+}
+
+/// The settings of the sink that determine how metrics are encoded.
+#[derive(Clone, Debug)]
+struct EncodingOptions {
+    namespace: Option<String>,
+    namespace_tag: Option<String>,
+    namespace_separator: String,
+    entity_id_tag: Option<String>,
+    dialect: Dialect,
+    value_delimiter: char,
+    tag_prefix: String,
+    default_sample_rates: DefaultSampleRates,
+    sample_rate: Option<f64>,
+    summary_quantile_name: String,
+    suppress_zero_counters: bool,
+    set_emission: SetEmission,
+    name_rewrites: Vec<(Regex, String)>,
+    empty_name_placeholder: Option<String>,
+}
+
+impl Default for EncodingOptions {
+    fn default() -> Self {
+        Self {
+            namespace: None,
+            namespace_tag: None,
+            namespace_separator: default_namespace_separator(),
+            entity_id_tag: None,
+            dialect: Dialect::default(),
+            value_delimiter: default_value_delimiter(),
+            tag_prefix: default_tag_prefix(),
+            default_sample_rates: DefaultSampleRates::default(),
+            sample_rate: None,
+            summary_quantile_name: default_summary_quantile_name(),
+            suppress_zero_counters: false,
+            set_emission: SetEmission::Members,
+            name_rewrites: Vec::new(),
+            empty_name_placeholder: None,
+        }
+    }
+}
+
+/// Encodes `event` with the sink's default settings, for benchmarks.
+#[doc(hidden)]
+pub fn encode_event_with_defaults(event: Event) -> Option<Vec<u8>> {
+    encode_event(event, &Default::default())
 }
 
-fn encode_event(event: Event, namespace: Option<&str>) -> Option<Vec<u8>> {
-    let mut buf = Vec::new();
+fn encode_event(event: Event, options: &EncodingOptions) -> Option<Vec<u8>> {
+    let default_sample_rates = options.default_sample_rates;
+
+    let mut metric = event.into_metric();
+    // Cumulative bucket counts would be sent as new observations every
+    // time, unless `absolute_to_incremental` turned them into deltas.
+    if let (MetricValue::AggregatedHistogram { .. }, MetricKind::Absolute) =
+        (&metric.value, &metric.kind)
+    {
+        emit!(StatsdInvalidMetricReceived {
+            value: &metric.value,
+            kind: &metric.kind,
+        });
+        return None;
+    }
+    if matches!(
+        metric.value,
+        MetricValue::AggregatedSummary { .. } | MetricValue::AggregatedHistogram { .. }
+    ) {
+        let body = expand_summary(metric, &options.summary_quantile_name)
+            .into_iter()
+            .flat_map(expand_histogram)
+            .filter_map(|metric| encode_event(Event::Metric(metric), options))
+            .flatten()
+            .collect::<Vec<_>>();
+        return if body.is_empty() { None } else { Some(body) };
+    }
+
+    for (pattern, replacement) in &options.name_rewrites {
+        if let Cow::Owned(name) = pattern.replace_all(&metric.name, replacement.as_str()) {
+            metric.name = name;
+        }
+    }
+
+    if metric.name.is_empty() {
+        match &options.empty_name_placeholder {
+            Some(placeholder) => metric.name = placeholder.clone(),
+            None => {
+                emit!(StatsdEmptyMetricName {
+                    value: &metric.value
+                });
+                return None;
+            }
+        }
+    }
+
+    if let Some(value) = non_finite_value(&metric.value) {
+        emit!(StatsdInvalidValue {
+            name: &metric.name,
+            value,
+        });
+        return None;
+    }
+
+    if options.suppress_zero_counters && metric.kind == MetricKind::Incremental {
+        if let MetricValue::Counter { value } = metric.value {
+            if value == 0.0 {
+                emit!(StatsdZeroCounterSuppressed { name: &metric.name });
+                return None;
+            }
+        }
+    }
+
+    if let Some(rate) = options.sample_rate {
+        // Sampling gauges and sets would lose their value, not just
+        // precision.
+        let sampled = matches!(
+            metric.value,
+            MetricValue::Counter { .. } | MetricValue::Distribution { .. }
+        );
+        if sampled && random::<f64>() >= rate {
+            emit!(StatsdMetricSampledOut { name: &metric.name });
+            return None;
+        }
+    }
+
+    let metric_namespace = options
+        .namespace_tag
+        .as_deref()
+        .and_then(|tag| take_tag(&mut metric.tags, tag));
+    let namespace = if take_tag(&mut metric.tags, NO_NAMESPACE_TAG).is_some() {
+        None
+    } else {
+        metric_namespace
+            .as_deref()
+            .or_else(|| options.namespace.as_deref())
+    };
+    let entity_id = match (&options.entity_id_tag, options.dialect) {
+        (Some(tag), Dialect::Dogstatsd) => take_tag(&mut metric.tags, tag),
+        _ => None,
+    };
+
+    let mut line = String::with_capacity(estimated_size(
+        &metric,
+        namespace,
+        entity_id.as_deref(),
+        options,
+    ));
+    match namespace {
+        Some(namespace) if namespace.is_empty() => {
+            warn!("Dropping empty namespace. This feature has been deprecated, and could be removed in the future.");
+        }
+        Some(namespace) => {
+            line.push_str(namespace);
+            line.push_str(&options.namespace_separator);
+        }
+        None => (),
+    }
+    let body_start = line.len();
 
-    let metric = event.as_metric();
-    match &metric.value {
+    let metric_type = match &metric.value {
         MetricValue::Counter { value } => {
-            push_event(&mut buf, &metric, value, "c", None);
+            // A sampled counter is sent as is, while the default rate
+            // applies to a counter that represents every event.
+            let (value, sample_rate) = match options.sample_rate {
+                Some(rate) => (*value, rate),
+                None => {
+                    let rate = default_sample_rates.counter.unwrap_or(1.0);
+                    (value * rate, rate)
+                }
+            };
+            push_event(
+                &mut line,
+                body_start,
+                &metric,
+                value,
+                "c",
+                Some(sample_rate),
+                entity_id.as_deref(),
+                options,
+            );
+            "counter"
         }
         MetricValue::Gauge { value } => {
             match metric.kind {
-                MetricKind::Incremental => {
-                    push_event(&mut buf, &metric, format!("{:+}", value), "g", None)
-                }
-                MetricKind::Absolute => push_event(&mut buf, &metric, value, "g", None),
+                MetricKind::Incremental => push_event(
+                    &mut line,
+                    body_start,
+                    &metric,
+                    format!("{:+}", value),
+                    "g",
+                    None,
+                    entity_id.as_deref(),
+                    options,
+                ),
+                MetricKind::Absolute => push_event(
+                    &mut line,
+                    body_start,
+                    &metric,
+                    value,
+                    "g",
+                    None,
+                    entity_id.as_deref(),
+                    options,
+                ),
             };
+            "gauge"
         }
         MetricValue::Distribution {
             values,
@@ -194,13 +1041,53 @@ fn encode_event(event: Event, namespace: Option<&str>) -> Option<Vec<u8>> {
                 StatisticKind::Summary => "d",
             };
             for (val, sample_rate) in values.iter().zip(sample_rates.iter()) {
-                push_event(&mut buf, &metric, val, metric_type, Some(*sample_rate));
+                let sample_rate = match sample_rate {
+                    1 => default_sample_rates.distribution.unwrap_or(1.0),
+                    rate => 1.0 / f64::from(*rate),
+                } * options.sample_rate.unwrap_or(1.0);
+                push_event(
+                    &mut line,
+                    body_start,
+                    &metric,
+                    val,
+                    metric_type,
+                    Some(sample_rate),
+                    entity_id.as_deref(),
+                    options,
+                );
             }
+            "distribution"
         }
         MetricValue::Set { values } => {
-            for val in values {
-                push_event(&mut buf, &metric, val, "s", None);
+            match options.set_emission {
+                SetEmission::Members => {
+                    for val in values {
+                        push_event(
+                            &mut line,
+                            body_start,
+                            &metric,
+                            val,
+                            "s",
+                            None,
+                            entity_id.as_deref(),
+                            options,
+                        );
+                    }
+                }
+                SetEmission::Count => {
+                    push_event(
+                        &mut line,
+                        body_start,
+                        &metric,
+                        values.len(),
+                        "g",
+                        None,
+                        entity_id.as_deref(),
+                        options,
+                    );
+                }
             }
+            "set"
         }
         _ => {
             emit!(StatsdInvalidMetricReceived {
@@ -212,243 +1099,2647 @@ fn encode_event(event: Event, namespace: Option<&str>) -> Option<Vec<u8>> {
         }
     };
 
-    let message = encode_namespace(namespace, '.', buf.join("|"));
+    // An empty set or distribution has no lines, and sending just the
+    // newline would only confuse receivers.
+    if line.len() == body_start {
+        return None;
+    }
 
-    let mut body: Vec<u8> = message.into_bytes();
-    body.push(b'\n');
+    emit!(StatsdMetricEncoded { metric_type });
 
-    Some(body)
+    line.push('\n');
+    Some(line.into_bytes())
 }
 
-impl Service<Vec<u8>> for StatsdSvc {
-    type Response = ();
-    type Error = crate::Error;
-    type Future = future::BoxFuture<'static, Result<(), Self::Error>>;
-
-    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        match &mut self.client {
-            Client::Tcp(service) => service.poll_ready(cx).map_err(Into::into),
-            Client::Udp(service) => service.poll_ready(cx).map_err(Into::into),
-            #[cfg(unix)]
-            Client::Unix(service) => service.poll_ready(cx).map_err(Into::into),
-        }
-    }
+/// Splits a summary into a gauge per quantile, named after
+/// `quantile_name`, and its `count` and `sum`. Those are counters once
+/// `absolute_to_incremental` has made them deltas, and gauges while they're
+/// running totals, as a counter would add the whole total every time.
+fn expand_summary(metric: Metric, quantile_name: &str) -> Vec<Metric> {
+    let (quantiles, values, count, sum) = match metric.value {
+        MetricValue::AggregatedSummary {
+            quantiles,
+            values,
+            count,
+            sum,
+        } => (quantiles, values, count, sum),
+        _ => return vec![metric],
+    };
 
-    fn call(&mut self, frame: Vec<u8>) -> Self::Future {
-        match &mut self.client {
-            Client::Tcp(service) => service.call(frame.into()).err_into().boxed(),
-            Client::Udp(service) => service.call(frame.into()).err_into().boxed(),
-            #[cfg(unix)]
-            Client::Unix(service) => service.call(frame.into()).err_into().boxed(),
+    let with_value = |name: String, kind, value| Metric {
+        name,
+        timestamp: metric.timestamp,
+        tags: metric.tags.clone(),
+        kind,
+        value,
+    };
+    let mut metrics = quantiles
+        .iter()
+        .zip(values.iter())
+        .map(|(quantile, value)| {
+            // Rounded to avoid names like `q99.89999999999999`.
+            let percentile = (quantile * 1000.0).round() / 10.0;
+            let name = quantile_name
+                .replace("{name}", &metric.name)
+                .replace("{quantile}", &percentile.to_string());
+            // Quantiles are values as of now, not adjustments.
+            with_value(
+                name,
+                MetricKind::Absolute,
+                MetricValue::Gauge { value: *value },
+            )
+        })
+        .collect::<Vec<_>>();
+    let total = |name, value| match metric.kind {
+        MetricKind::Incremental => with_value(
+            name,
+            MetricKind::Incremental,
+            MetricValue::Counter { value },
+        ),
+        MetricKind::Absolute => {
+            with_value(name, MetricKind::Absolute, MetricValue::Gauge { value })
         }
-    }
+    };
+    metrics.push(total(format!("{}.count", metric.name), f64::from(count)));
+    metrics.push(total(format!("{}.sum", metric.name), sum));
+    metrics
 }
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use crate::{
-        event::{metric::MetricKind, metric::MetricValue, metric::StatisticKind, Metric},
-        test_util::*,
-        Event,
+/// Splits a histogram into a distribution per bucket, holding the bucket's
+/// upper bound sampled as many times as the bucket has observations.
+/// Observations above the last bucket are put in it, as their values aren't
+/// known.
+fn expand_histogram(metric: Metric) -> Vec<Metric> {
+    let (buckets, counts, count, sum) = match metric.value {
+        MetricValue::AggregatedHistogram {
+            buckets,
+            counts,
+            count,
+            sum,
+        } => (buckets, counts, count, sum),
+        _ => return vec![metric],
     };
-    use bytes::Bytes;
-    use futures::{compat::Sink01CompatExt, stream, SinkExt, StreamExt, TryStreamExt};
-    use futures01::sync::mpsc;
-    use tokio::net::UdpSocket;
-    use tokio_util::{codec::BytesCodec, udp::UdpFramed};
-
-    #[cfg(feature = "sources-statsd")]
-    use {crate::sources::statsd::parser::parse, std::str::from_utf8};
 
-    #[test]
-    fn generate_config() {
-        crate::test_util::test_generate_config::<StatsdSinkConfig>();
+    // Bucket counts are cumulative.
+    let mut observations = Vec::with_capacity(buckets.len());
+    let mut below = 0;
+    for (bucket, cumulative) in buckets.iter().zip(counts.iter()) {
+        observations.push((*bucket, cumulative.saturating_sub(below)));
+        below = below.max(*cumulative);
+    }
+    let above = count.saturating_sub(below);
+    match observations.last_mut() {
+        Some((_, last)) => *last += above,
+        // Without buckets, the mean is all there is to go on.
+        None if count > 0 => observations.push((sum / f64::from(count), count)),
+        None => (),
     }
 
-    fn tags() -> BTreeMap<String, String> {
-        vec![
-            ("normal_tag".to_owned(), "value".to_owned()),
-            ("true_tag".to_owned(), "true".to_owned()),
-            ("empty_tag".to_owned(), "".to_owned()),
-        ]
+    observations
         .into_iter()
-        .collect()
+        .filter(|(_, count)| *count > 0)
+        .map(|(value, count)| Metric {
+            name: metric.name.clone(),
+            timestamp: metric.timestamp,
+            tags: metric.tags.clone(),
+            kind: metric.kind.clone(),
+            value: MetricValue::Distribution {
+                values: vec![value],
+                sample_rates: vec![count],
+                statistic: StatisticKind::Histogram,
+            },
+        })
+        .collect()
+}
+
+type SeriesKey = (String, Option<BTreeMap<String, String>>);
+
+/// Drops absolute gauges that repeat the value last sent for the same
+/// series, unless `max_interval` has passed since it was sent, so a
+/// receiver doesn't consider the series stale. Values sent longer ago than
+/// that are forgotten, as they no longer suppress anything.
+#[derive(Debug)]
+struct UnchangedGauges {
+    last_sent: SeriesCache<f64>,
+}
+
+impl UnchangedGauges {
+    fn new(max_interval: Duration) -> Self {
+        Self {
+            last_sent: SeriesCache::new(max_interval),
+        }
+    }
+
+    fn filter(&mut self, event: Event) -> Option<Event> {
+        self.filter_at(event, Instant::now())
+    }
+
+    /// `filter`, as of `now`.
+    fn filter_at(&mut self, event: Event, now: Instant) -> Option<Event> {
+        let metric = event.as_metric();
+        let value = match metric.value {
+            MetricValue::Gauge { value } if metric.kind == MetricKind::Absolute => value,
+            _ => return Some(event),
+        };
+
+        let key = (metric.name.clone(), metric.tags.clone());
+        if self.last_sent.get(&key, now) == Some(&value) {
+            emit!(StatsdUnchangedGaugeSuppressed { name: &metric.name });
+            None
+        } else {
+            self.last_sent.insert(key, value, now);
+            Some(event)
+        }
+    }
+}
+
+/// Per-series state, forgotten once a series hasn't been updated for `ttl`,
+/// so series that stop being sent don't hold on to memory.
+#[derive(Debug)]
+struct SeriesCache<V> {
+    ttl: Duration,
+    entries: HashMap<SeriesKey, (V, Instant)>,
+    last_swept: Option<Instant>,
+}
+
+impl<V> SeriesCache<V> {
+    fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: HashMap::new(),
+            last_swept: None,
+        }
+    }
+
+    fn is_live(&self, updated: Instant, now: Instant) -> bool {
+        now.saturating_duration_since(updated) < self.ttl
+    }
+
+    /// The value of the series, unless it has expired as of `now`.
+    fn get(&self, key: &SeriesKey, now: Instant) -> Option<&V> {
+        match self.entries.get(key) {
+            Some((value, updated)) if self.is_live(*updated, now) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Updates the series as of `now`, returning its previous value unless
+    /// that had expired.
+    fn insert(&mut self, key: SeriesKey, value: V, now: Instant) -> Option<V> {
+        self.sweep(now);
+        match self.entries.insert(key, (value, now)) {
+            Some((previous, updated)) if self.is_live(updated, now) => Some(previous),
+            _ => None,
+        }
+    }
+
+    /// Drops the expired series. This is done at most once per `ttl`, so
+    /// its cost is spread over many updates.
+    fn sweep(&mut self, now: Instant) {
+        match self.last_swept {
+            Some(swept) if self.is_live(swept, now) => (),
+            _ => {
+                let ttl = self.ttl;
+                self.entries
+                    .retain(|_, (_, updated)| now.saturating_duration_since(*updated) < ttl);
+                self.last_swept = Some(now);
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+/// How long the baseline of a series is kept without a new value. A series
+/// seen again after that starts over, as if it were new.
+const DELTA_SERIES_TTL: Duration = Duration::from_secs(15 * 60);
+
+/// Turns absolute counters, histograms and summaries into what was added
+/// to them since the last value seen for the same series, which is what
+/// StatsD receivers expect.
+#[derive(Debug)]
+struct AbsoluteDeltas {
+    previous: SeriesCache<MetricValue>,
+}
+
+impl AbsoluteDeltas {
+    fn new(ttl: Duration) -> Self {
+        Self {
+            previous: SeriesCache::new(ttl),
+        }
+    }
+
+    /// The first value of a series only sets the baseline for the next, so
+    /// it isn't sent.
+    fn to_incremental(&mut self, event: Event) -> Option<Event> {
+        self.to_incremental_at(event, Instant::now())
+    }
+
+    /// `to_incremental`, as of `now`.
+    fn to_incremental_at(&mut self, event: Event, now: Instant) -> Option<Event> {
+        let mut metric = event.into_metric();
+        let tracked = matches!(
+            metric.value,
+            MetricValue::Counter { .. }
+                | MetricValue::AggregatedHistogram { .. }
+                | MetricValue::AggregatedSummary { .. }
+        );
+        if metric.kind != MetricKind::Absolute || !tracked {
+            return Some(Event::Metric(metric));
+        }
+
+        let key = (metric.name.clone(), metric.tags.clone());
+        let previous = self.previous.insert(key, metric.value.clone(), now)?;
+        metric.kind = MetricKind::Incremental;
+        metric.value = match (metric.value, previous) {
+            (MetricValue::Counter { value }, MetricValue::Counter { value: previous })
+                if value >= previous =>
+            {
+                MetricValue::Counter {
+                    value: value - previous,
+                }
+            }
+            (
+                MetricValue::AggregatedHistogram {
+                    buckets,
+                    counts,
+                    count,
+                    sum,
+                },
+                MetricValue::AggregatedHistogram {
+                    buckets: previous_buckets,
+                    counts: previous_counts,
+                    count: previous_count,
+                    sum: previous_sum,
+                },
+            ) if buckets == previous_buckets
+                && count >= previous_count
+                && counts
+                    .iter()
+                    .zip(previous_counts.iter())
+                    .all(|(count, previous)| count >= previous) =>
+            {
+                MetricValue::AggregatedHistogram {
+                    counts: counts
+                        .iter()
+                        .zip(previous_counts.iter())
+                        .map(|(count, previous)| count - previous)
+                        .collect(),
+                    count: count - previous_count,
+                    sum: sum - previous_sum,
+                    buckets,
+                }
+            }
+            // Quantiles describe the current window, so only the count and
+            // sum are cumulative.
+            (
+                MetricValue::AggregatedSummary {
+                    quantiles,
+                    values,
+                    count,
+                    sum,
+                },
+                MetricValue::AggregatedSummary {
+                    count: previous_count,
+                    sum: previous_sum,
+                    ..
+                },
+            ) if count >= previous_count => MetricValue::AggregatedSummary {
+                quantiles,
+                values,
+                count: count - previous_count,
+                sum: sum - previous_sum,
+            },
+            // The series went down or changed shape, so it was reset and
+            // everything in it is new.
+            (value, _) => value,
+        };
+        Some(Event::Metric(metric))
+    }
+}
+
+/// Sorts the lines of a frame by the metric name before `value_delimiter`,
+/// keeping lines with the same name in their original order.
+fn sort_by_name(frame: Vec<u8>, value_delimiter: char) -> Vec<u8> {
+    let frame = match String::from_utf8(frame) {
+        Ok(frame) => frame,
+        Err(error) => return error.into_bytes(),
+    };
+    let mut lines = frame.lines().collect::<Vec<_>>();
+    lines.sort_by_key(|line| line.split(value_delimiter).next());
+
+    let mut sorted = lines.join("\n").into_bytes();
+    sorted.push(b'\n');
+    sorted
+}
+
+/// Orders the lines of a frame by metric type, keeping lines of the same
+/// type in their original order, so servers applying a packet in order see
+/// counters before gauges.
+fn sort_by_type(frame: Vec<u8>) -> Vec<u8> {
+    let frame = match String::from_utf8(frame) {
+        Ok(frame) => frame,
+        Err(error) => return error.into_bytes(),
+    };
+    let mut lines = frame.lines().collect::<Vec<_>>();
+    lines.sort_by_key(|line| match line.split('|').nth(1) {
+        Some("c") => 0,
+        Some("g") => 1,
+        Some("d") | Some("h") | Some("ms") => 2,
+        Some("s") => 3,
+        _ => 4,
+    });
+
+    let mut sorted = lines.join("\n").into_bytes();
+    sorted.push(b'\n');
+    sorted
+}
+
+/// Removes `tag` from `tags`, dropping the map entirely once it's empty so
+/// no bare `#` is sent.
+fn take_tag(tags: &mut Option<BTreeMap<String, String>>, tag: &str) -> Option<String> {
+    let map = tags.as_mut()?;
+    let value = map.remove(tag);
+    if map.is_empty() {
+        *tags = None;
+    }
+    value
+}
+
+/// Finds a NaN or infinite value, which StatsD servers can't parse.
+fn non_finite_value(value: &MetricValue) -> Option<f64> {
+    let values: &[f64] = match value {
+        MetricValue::Counter { value } | MetricValue::Gauge { value } => {
+            std::slice::from_ref(value)
+        }
+        MetricValue::Distribution { values, .. } => values.as_slice(),
+        _ => &[],
+    };
+    values.iter().copied().find(|value| !value.is_finite())
+}
+
+impl Service<Vec<u8>> for StatsdSvc {
+    type Response = ();
+    type Error = crate::Error;
+    type Future = future::BoxFuture<'static, Result<(), Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if let Some(pacing) = &mut self.pacing {
+            futures::ready!(pacing.poll_ready(cx));
+        }
+        match &mut self.client {
+            Client::Tcp(service) => service.poll_ready(cx).map_err(Into::into),
+            Client::Udp(service) => service.poll_ready(cx).map_err(Into::into),
+            #[cfg(unix)]
+            Client::Unix(service) => service.poll_ready(cx).map_err(Into::into),
+            #[cfg(unix)]
+            Client::Fifo(service) => service.poll_ready(cx).map_err(Into::into),
+            #[cfg(test)]
+            Client::Memory(_) => Poll::Ready(Ok(())),
+        }
+    }
+
+    fn call(&mut self, frame: Vec<u8>) -> Self::Future {
+        if let Some(buffered) = &self.buffered {
+            buffered.remove(frame.len());
+        }
+        if let Some(pacing) = &mut self.pacing {
+            pacing.sent();
+        }
+        let frame = match self.sort_by_name {
+            Some(value_delimiter) => sort_by_name(frame, value_delimiter),
+            None => frame,
+        };
+        let frame = if self.sort_by_type {
+            sort_by_type(frame)
+        } else {
+            frame
+        };
+
+        let spill = self
+            .fallback
+            .as_ref()
+            .map(|fallback| (Arc::clone(fallback), frame.clone()));
+
+        let future = match &mut self.client {
+            Client::Tcp(service) => service.call(frame.into()).err_into().boxed(),
+            Client::Udp(service) => service.call(frame.into()).err_into().boxed(),
+            #[cfg(unix)]
+            Client::Unix(service) => service.call(frame.into()).err_into().boxed(),
+            #[cfg(unix)]
+            Client::Fifo(service) => service.call(frame.into()).err_into().boxed(),
+            #[cfg(test)]
+            Client::Memory(config) => {
+                config.push(frame);
+                future::ok(()).boxed()
+            }
+        };
+
+        match spill {
+            None => future,
+            Some((fallback, frame)) => async move {
+                match future.await {
+                    Ok(()) => Ok(()),
+                    Err(error) => match fallback.spill(&frame).await {
+                        Ok(()) => {
+                            emit!(StatsdBatchSpilled {
+                                byte_size: frame.len(),
+                                path: &fallback.path,
+                                error,
+                            });
+                            Ok(())
+                        }
+                        Err(spill_error) => {
+                            emit!(StatsdSpillFailed {
+                                error: spill_error,
+                                path: &fallback.path,
+                            });
+                            Err(error)
+                        }
+                    },
+                }
+            }
+            .boxed(),
+        }
+    }
+}
+
+/// Reports how many events were waiting when the sink was closed, and how
+/// long it took to send them.
+struct ShutdownDrain {
+    inner: BatchSink<StatsdSvc, Buffer, Vec<u8>>,
+    /// When the drain started, and the events pending then.
+    started: Option<(Instant, usize)>,
+}
+
+impl ShutdownDrain {
+    fn new(inner: BatchSink<StatsdSvc, Buffer, Vec<u8>>) -> Self {
+        Self {
+            inner,
+            started: None,
+        }
+    }
+}
+
+impl Sink for ShutdownDrain {
+    type SinkItem = Vec<u8>;
+    type SinkError = crate::Error;
+
+    fn start_send(&mut self, item: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
+        self.inner.start_send(item)
+    }
+
+    fn poll_complete(&mut self) -> Poll01<(), Self::SinkError> {
+        self.inner.poll_complete()
+    }
+
+    fn close(&mut self) -> Poll01<(), Self::SinkError> {
+        if self.started.is_none() {
+            self.started = Some((Instant::now(), self.inner.pending_events()));
+        }
+        try_ready!(self.inner.close());
+
+        if let Some((started, pending_events)) = self.started.take() {
+            emit!(StatsdShutdownDrain {
+                pending_events,
+                duration: started.elapsed(),
+            });
+        }
+        Ok(Async::Ready(()))
+    }
+}
+
+/// Tracks how many encoded bytes are waiting in the batch buffer.
+#[derive(Clone, Default)]
+struct BufferedBytes(Arc<AtomicUsize>);
+
+impl BufferedBytes {
+    fn add(&self, byte_size: usize) {
+        let byte_size = self.0.fetch_add(byte_size, Ordering::Relaxed) + byte_size;
+        emit!(StatsdBufferedBytes { byte_size });
+    }
+
+    /// Saturates at zero, so removing a frame twice can't wrap the count
+    /// around to a huge value.
+    fn remove(&self, byte_size: usize) {
+        let previous = self
+            .0
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |buffered| {
+                Some(buffered.saturating_sub(byte_size))
+            })
+            // The closure always returns `Some`.
+            .unwrap_or_else(|previous| previous);
+        emit!(StatsdBufferedBytes {
+            byte_size: previous.saturating_sub(byte_size)
+        });
+    }
+
+    /// Removes what the overflow policy drops, as it will never be sent.
+    fn drop_hook(&self) -> impl FnMut(Dropped<Buffer>) + Send + 'static {
+        let buffered = self.clone();
+        move |dropped| match dropped {
+            Dropped::Event(frame) | Dropped::Batch(frame) => buffered.remove(frame.len()),
+        }
+    }
+
+    #[cfg(test)]
+    fn get(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// An append-only file holding the batches that could not be sent, each
+/// prefixed with its length as a big endian `u32`. The file is never
+/// rewritten in place: replaying first moves it aside, and anything written
+/// back goes to a temporary file that is then renamed over it, so a crash
+/// at any point leaves every batch on disk.
+struct DiskFallback {
+    path: PathBuf,
+    /// Where the batches being replayed are moved to, until sent.
+    replay_path: PathBuf,
+    max_bytes: u64,
+    lock: Mutex<()>,
+}
+
+impl DiskFallback {
+    fn new(path: PathBuf, max_bytes: u64) -> Self {
+        Self {
+            replay_path: with_suffix(&path, ".replay"),
+            path,
+            max_bytes,
+            lock: Mutex::new(()),
+        }
+    }
+
+    async fn spill(&self, frame: &[u8]) -> io::Result<()> {
+        let mut record = Vec::with_capacity(frame.len() + 4);
+        encode_spilled(frame, &mut record);
+        let record_size = record.len() as u64;
+        if record_size > self.max_bytes {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "batch is larger than disk_fallback_max_bytes",
+            ));
+        }
+
+        let _guard = self.lock.lock().await;
+        let size = match tokio::fs::metadata(&self.path).await {
+            Ok(metadata) => metadata.len(),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => 0,
+            Err(error) => return Err(error),
+        };
+        if size + record_size > self.max_bytes {
+            self.drop_oldest(size + record_size - self.max_bytes)
+                .await?;
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+        file.write_all(&record).await?;
+        file.flush().await
+    }
+
+    /// Drops the oldest spilled batches until at least `excess` bytes are
+    /// freed. Must be called with `lock` held.
+    async fn drop_oldest(&self, excess: u64) -> io::Result<()> {
+        let mut spilled = BufReader::new(File::open(&self.path).await?);
+        let (mut count, mut byte_size) = (0, 0);
+        while byte_size < excess {
+            match read_spilled(&mut spilled).await? {
+                Some(frame) => {
+                    count += 1;
+                    byte_size += frame.len() as u64 + 4;
+                }
+                None => break,
+            }
+        }
+
+        let temp_path = with_suffix(&self.path, ".tmp");
+        let mut temp = File::create(&temp_path).await?;
+        tokio::io::copy(&mut spilled, &mut temp).await?;
+        temp.sync_all().await?;
+        tokio::fs::rename(&temp_path, &self.path).await?;
+        emit!(StatsdSpilledBatchesDropped { count, byte_size });
+        Ok(())
+    }
+
+    /// Sends the spilled batches through `service`, putting the ones that
+    /// still fail back on disk for the next attempt.
+    async fn replay(&self, service: &mut StatsdSvc) {
+        if let Err(error) = self.try_replay(service).await {
+            emit!(StatsdSpillFailed {
+                error,
+                path: &self.path,
+            });
+        }
+    }
+
+    async fn try_replay(&self, service: &mut StatsdSvc) -> io::Result<()> {
+        {
+            let _guard = self.lock.lock().await;
+            // Batches left aside by a replay cut short by a crash are
+            // replayed first.
+            if tokio::fs::metadata(&self.replay_path).await.is_err() {
+                match tokio::fs::rename(&self.path, &self.replay_path).await {
+                    Ok(()) => (),
+                    Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(()),
+                    Err(error) => return Err(error),
+                }
+            }
+        }
+
+        // Sending may take long, for instance to connect, so it's done
+        // without the lock to leave new batches free to spill meanwhile.
+        let mut replaying = BufReader::new(File::open(&self.replay_path).await?);
+        let mut sent = 0;
+        let unsent = loop {
+            let frame = match read_spilled(&mut replaying).await? {
+                Some(frame) => frame,
+                None => break None,
+            };
+            let ready = future::poll_fn(|cx| service.poll_ready(cx)).await;
+            if ready.is_err() || service.call(frame.clone()).await.is_err() {
+                break Some(frame);
+            }
+            sent += 1;
+        };
+
+        if let Some(frame) = unsent {
+            self.restore(&frame, replaying).await?;
+        }
+        tokio::fs::remove_file(&self.replay_path).await?;
+        if sent > 0 {
+            emit!(StatsdSpilledBatchesReplayed { count: sent });
+        }
+        Ok(())
+    }
+
+    /// Puts `frame` and the rest of the batches being replayed back in the
+    /// spill file, ahead of those spilled since.
+    async fn restore(&self, frame: &[u8], mut unsent: impl AsyncRead + Unpin) -> io::Result<()> {
+        let _guard = self.lock.lock().await;
+        let temp_path = with_suffix(&self.path, ".tmp");
+        let mut temp = File::create(&temp_path).await?;
+        let mut record = Vec::with_capacity(frame.len() + 4);
+        encode_spilled(frame, &mut record);
+        temp.write_all(&record).await?;
+        tokio::io::copy(&mut unsent, &mut temp).await?;
+        match File::open(&self.path).await {
+            Ok(mut spilled) => {
+                tokio::io::copy(&mut spilled, &mut temp).await?;
+            }
+            Err(error) if error.kind() == io::ErrorKind::NotFound => (),
+            Err(error) => return Err(error),
+        }
+        temp.sync_all().await?;
+        tokio::fs::rename(&temp_path, &self.path).await
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    path.into()
+}
+
+async fn replay_spilled(fallback: Weak<DiskFallback>, mut service: StatsdSvc, interval: Duration) {
+    loop {
+        delay_for(interval).await;
+        match fallback.upgrade() {
+            Some(fallback) => fallback.replay(&mut service).await,
+            None => break,
+        }
+    }
+}
+
+fn encode_spilled(frame: &[u8], buf: &mut Vec<u8>) {
+    buf.extend_from_slice(&(frame.len() as u32).to_be_bytes());
+    buf.extend_from_slice(frame);
+}
+
+/// Reads the next spilled batch, if any. A batch cut short by a crash ends
+/// the file.
+async fn read_spilled(reader: &mut (impl AsyncRead + Unpin)) -> io::Result<Option<Vec<u8>>> {
+    let mut len = [0; 4];
+    match reader.read_exact(&mut len).await {
+        Ok(_) => (),
+        Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(error) => return Err(error),
+    }
+    let len = u32::from_be_bytes(len) as usize;
+    let mut frame = vec![0; len];
+    match reader.read_exact(&mut frame).await {
+        Ok(_) => Ok(Some(frame)),
+        Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => {
+            warn!(message = "Dropping truncated spilled batch.", len);
+            Ok(None)
+        }
+        Err(error) => Err(error),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        event::{metric::MetricKind, metric::MetricValue, metric::StatisticKind, Metric},
+        test_util::*,
+        Event,
+    };
+    use futures::stream;
+
+    #[cfg(feature = "sources-statsd")]
+    use {crate::sources::statsd::parser::parse, std::str::from_utf8};
+
+    #[test]
+    fn generate_config() {
+        crate::test_util::test_generate_config::<StatsdSinkConfig>();
+    }
+
+    fn test_config(mode: Mode) -> StatsdSinkConfig {
+        StatsdSinkConfig {
+            namespace: None,
+            namespace_tag: None,
+            namespace_separator: default_namespace_separator(),
+            entity_id_tag: None,
+            batch: Default::default(),
+            batch_disabled: false,
+            overflow_policy: OverflowPolicy::Block,
+            align_batch_timeout: false,
+            adaptive_timeout: false,
+            idle_flush_ms: None,
+            soft_max_bytes: None,
+            max_packets_per_flush: None,
+            dialect: Dialect::Statsd,
+            disk_fallback_path: None,
+            disk_fallback_max_bytes: default_disk_fallback_max_bytes(),
+            value_delimiter: default_value_delimiter(),
+            tag_prefix: default_tag_prefix(),
+            default_sample_rates: Default::default(),
+            sample_rate: None,
+            summary_quantile_name: default_summary_quantile_name(),
+            suppress_zero_counters: false,
+            absolute_to_incremental: false,
+            unchanged_gauge_interval_secs: None,
+            set_emission: SetEmission::Members,
+            name_rewrites: Vec::new(),
+            empty_name_placeholder: None,
+            sort_by_name: false,
+            sort_by_type: false,
+            fallback: None,
+            mode,
+        }
+    }
+
+    #[test]
+    fn parses_tcp_options() {
+        let mode: Mode = toml::from_str(
+            r#"
+            mode = "tcp"
+            address = "127.0.0.1:8125"
+            connection_mode = "persistent"
+            idle_timeout_secs = 30
+            tos = 16
+            "#,
+        )
+        .unwrap();
+
+        let config = match mode {
+            Mode::Tcp(config) => config,
+            _ => panic!("Not the TCP mode"),
+        };
+        assert_eq!(config.socket.address, "127.0.0.1:8125");
+        assert_eq!(config.socket.tos, Some(16));
+        assert_eq!(config.connection_mode, ConnectionMode::Persistent);
+        assert_eq!(config.idle_timeout_secs, Some(30));
+        assert!(config.write_coalescing);
+    }
+
+    async fn build_error(batch: BatchConfig) -> String {
+        let config = StatsdSinkConfig {
+            batch,
+            ..test_config(Mode::Udp(UdpSinkConfig::new(default_address().to_string())))
+        };
+        match config.build(SinkContext::new_test()).await {
+            Ok(_) => panic!("build should have failed"),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct CapturedLogs(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for CapturedLogs {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn logs_selected_mode() {
+        let logs = CapturedLogs::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let addr = next_addr();
+        let config = test_config(Mode::Tcp(StatsdTcpConfig::new(addr.to_string())));
+        config.build(SinkContext::new_test()).await.unwrap();
+
+        let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        assert!(logs.contains("mode=tcp"), "{}", logs);
+        assert!(logs.contains(&format!("target={}", addr)), "{}", logs);
+    }
+
+    #[tokio::test]
+    async fn warns_about_tiny_batch() {
+        let logs = CapturedLogs::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let config = StatsdSinkConfig {
+            namespace: Some("vector".into()),
+            batch: BatchConfig {
+                max_bytes: Some(10),
+                ..Default::default()
+            },
+            ..test_config(Mode::Udp(UdpSinkConfig::new(default_address().to_string())))
+        };
+        config.build(SinkContext::new_test()).await.unwrap();
+
+        let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        assert!(
+            logs.contains("too small to hold a typical metric"),
+            "{}",
+            logs
+        );
+        assert!(logs.contains("needed=71"), "{}", logs);
+    }
+
+    #[tokio::test]
+    async fn rejects_zero_byte_batch() {
+        let error = build_error(BatchConfig {
+            max_bytes: Some(0),
+            ..Default::default()
+        })
+        .await;
+        assert_eq!(error, "Batch max_bytes must be at least 6 bytes");
+    }
+
+    #[tokio::test]
+    async fn rejects_zero_event_batch() {
+        let error = build_error(BatchConfig {
+            max_events: Some(0),
+            ..Default::default()
+        })
+        .await;
+        assert_eq!(error, "Batch max_events must be greater than zero");
+    }
+
+    #[tokio::test]
+    async fn rejects_zero_batch_timeout() {
+        let error = build_error(BatchConfig {
+            timeout_secs: Some(0),
+            ..Default::default()
+        })
+        .await;
+        assert_eq!(error, "Batch timeout_secs must be greater than zero");
+    }
+
+    #[test]
+    fn rejects_soft_max_bytes_out_of_range() {
+        let batch = BatchSettings::<Buffer>::default()
+            .bytes(1300)
+            .events(1000)
+            .timeout(1);
+        assert!(validate_batch(&batch, Some(500)).is_ok());
+        for soft_max_bytes in &[0, 1301] {
+            assert_eq!(
+                validate_batch(&batch, Some(*soft_max_bytes))
+                    .unwrap_err()
+                    .to_string(),
+                "soft_max_bytes must be between 1 and the batch max_bytes (1300)"
+            );
+        }
+    }
+
+    fn tags() -> BTreeMap<String, String> {
+        vec![
+            ("normal_tag".to_owned(), "value".to_owned()),
+            ("true_tag".to_owned(), "true".to_owned()),
+            ("empty_tag".to_owned(), "".to_owned()),
+        ]
+        .into_iter()
+        .collect()
+    }
+
+    #[test]
+    fn test_encode_tags() {
+        assert_eq!(
+            &encode_tags(&tags(), Dialect::Statsd),
+            "empty_tag:,normal_tag:value,true_tag"
+        );
+    }
+
+    #[test]
+    fn test_encode_tags_dogstatsd() {
+        let tags = vec![
+            ("path".to_owned(), "/var/log/some file".to_owned()),
+            ("normal_tag".to_owned(), "value".to_owned()),
+            ("true_tag".to_owned(), "true".to_owned()),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(
+            &encode_tags(&tags, Dialect::Dogstatsd),
+            "normal_tag:value,path:/var/log/some%20file,true_tag"
+        );
+
+        let tags = vec![("weird".to_owned(), "a b,c|d#e".to_owned())]
+            .into_iter()
+            .collect();
+        assert_eq!(&encode_tags(&tags, Dialect::Statsd), "weird:a b,c|d#e");
+        assert_eq!(
+            &encode_tags(&tags, Dialect::Dogstatsd),
+            "weird:a%20b%2Cc%7Cd%23e"
+        );
+    }
+
+    #[cfg(feature = "sources-statsd")]
+    #[test]
+    fn test_encode_counter() {
+        let metric1 = Metric {
+            name: "counter".to_owned(),
+            timestamp: None,
+            tags: Some(tags()),
+            kind: MetricKind::Incremental,
+            value: MetricValue::Counter { value: 1.5 },
+        };
+        let event = Event::Metric(metric1.clone());
+        let frame = &encode_event(event, &Default::default()).unwrap();
+        let metric2 = parse(from_utf8(&frame).unwrap().trim()).unwrap();
+        assert_eq!(metric1, metric2);
+    }
+
+    #[cfg(feature = "sources-statsd")]
+    #[test]
+    fn test_encode_absolute_counter() {
+        let metric1 = Metric {
+            name: "counter".to_owned(),
+            timestamp: None,
+            tags: None,
+            kind: MetricKind::Absolute,
+            value: MetricValue::Counter { value: 1.5 },
+        };
+        let event = Event::Metric(metric1);
+        let frame = &encode_event(event, &Default::default()).unwrap();
+        // The statsd parser will parse the counter as Incremental,
+        // so we can't compare it with the parsed value.
+        assert_eq!("counter:1.5|c\n", from_utf8(&frame).unwrap());
+    }
+
+    #[test]
+    fn test_encode_drops_non_finite_values() {
+        for value in &[f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            let event = Event::Metric(Metric {
+                name: "gauge".to_owned(),
+                timestamp: None,
+                tags: None,
+                kind: MetricKind::Absolute,
+                value: MetricValue::Gauge { value: *value },
+            });
+            assert_eq!(encode_event(event, &Default::default()), None);
+        }
+
+        let event = Event::Metric(Metric {
+            name: "histogram".to_owned(),
+            timestamp: None,
+            tags: None,
+            kind: MetricKind::Incremental,
+            value: MetricValue::Distribution {
+                values: vec![1.0, f64::NAN],
+                sample_rates: vec![1, 1],
+                statistic: StatisticKind::Histogram,
+            },
+        });
+        assert_eq!(encode_event(event, &Default::default()), None);
+    }
+
+    #[test]
+    fn test_encode_default_sample_rates() {
+        let options = EncodingOptions {
+            default_sample_rates: DefaultSampleRates {
+                counter: Some(0.5),
+                distribution: Some(0.1),
+            },
+            ..Default::default()
+        };
+
+        let event = Event::Metric(Metric {
+            name: "histogram".to_owned(),
+            timestamp: None,
+            tags: None,
+            kind: MetricKind::Incremental,
+            value: MetricValue::Distribution {
+                values: vec![1.5, 2.0],
+                sample_rates: vec![1, 4],
+                statistic: StatisticKind::Histogram,
+            },
+        });
+        let frame = encode_event(event, &options).unwrap();
+        assert_eq!(
+            String::from_utf8(frame).unwrap(),
+            "histogram:1.5|h|@0.1|histogram:2|h|@0.25\n"
+        );
+
+        let event = Event::Metric(Metric {
+            name: "counter".to_owned(),
+            timestamp: None,
+            tags: None,
+            kind: MetricKind::Incremental,
+            value: MetricValue::Counter { value: 10.0 },
+        });
+        let frame = encode_event(event, &options).unwrap();
+        assert_eq!(String::from_utf8(frame).unwrap(), "counter:5|c|@0.5\n");
+    }
+
+    #[test]
+    fn rejects_sample_rates_out_of_range() {
+        for rate in &[0.0, -0.5, 1.5] {
+            let rates = DefaultSampleRates {
+                counter: Some(*rate),
+                distribution: None,
+            };
+            assert!(validate_sample_rates(&rates, None).is_err());
+            assert!(validate_sample_rates(&Default::default(), Some(*rate)).is_err());
+        }
+        let rates = DefaultSampleRates {
+            counter: Some(1.0),
+            distribution: Some(0.1),
+        };
+        assert!(validate_sample_rates(&rates, Some(0.5)).is_ok());
+    }
+
+    #[test]
+    fn test_encode_sample_rate() {
+        let counter = || {
+            Event::Metric(Metric {
+                name: "counter".to_owned(),
+                timestamp: None,
+                tags: None,
+                kind: MetricKind::Incremental,
+                value: MetricValue::Counter { value: 1.0 },
+            })
+        };
+        let timer = || {
+            Event::Metric(Metric {
+                name: "timer".to_owned(),
+                timestamp: None,
+                tags: None,
+                kind: MetricKind::Incremental,
+                value: MetricValue::Distribution {
+                    values: vec![2.0],
+                    sample_rates: vec![1],
+                    statistic: StatisticKind::Histogram,
+                },
+            })
+        };
+
+        let all = EncodingOptions {
+            sample_rate: Some(1.0),
+            ..Default::default()
+        };
+        assert_eq!(
+            encode_event(counter(), &all),
+            Some(b"counter:1|c\n".to_vec())
+        );
+        assert_eq!(encode_event(timer(), &all), Some(b"timer:2|h\n".to_vec()));
+
+        let half = EncodingOptions {
+            sample_rate: Some(0.5),
+            ..Default::default()
+        };
+        let mut kept = 0;
+        for _ in 0..100 {
+            if let Some(frame) = encode_event(counter(), &half) {
+                assert_eq!(frame, b"counter:1|c|@0.5\n".to_vec());
+                kept += 1;
+            }
+            if let Some(frame) = encode_event(timer(), &half) {
+                assert_eq!(frame, b"timer:2|h|@0.5\n".to_vec());
+            }
+        }
+        assert!(kept > 0 && kept < 100);
+    }
+
+    #[test]
+    fn sample_rate_skips_gauges_and_sets() {
+        let options = EncodingOptions {
+            sample_rate: Some(0.01),
+            ..Default::default()
+        };
+        for _ in 0..100 {
+            let gauge = Event::Metric(Metric {
+                name: "gauge".to_owned(),
+                timestamp: None,
+                tags: None,
+                kind: MetricKind::Absolute,
+                value: MetricValue::Gauge { value: 1.5 },
+            });
+            assert_eq!(
+                encode_event(gauge, &options),
+                Some(b"gauge:1.5|g\n".to_vec())
+            );
+            let set = Event::Metric(Metric {
+                name: "set".to_owned(),
+                timestamp: None,
+                tags: None,
+                kind: MetricKind::Incremental,
+                value: MetricValue::Set {
+                    values: vec!["abc".to_owned()].into_iter().collect(),
+                },
+            });
+            assert_eq!(encode_event(set, &options), Some(b"set:abc|s\n".to_vec()));
+        }
+    }
+
+    #[test]
+    fn test_encode_value_delimiter() {
+        let event = Event::Metric(Metric {
+            name: "histogram".to_owned(),
+            timestamp: None,
+            tags: Some(tags()),
+            kind: MetricKind::Incremental,
+            value: MetricValue::Distribution {
+                values: vec![1.5],
+                sample_rates: vec![2],
+                statistic: StatisticKind::Histogram,
+            },
+        });
+        let options = EncodingOptions {
+            namespace: Some("vector".into()),
+            value_delimiter: '=',
+            ..Default::default()
+        };
+        let frame = encode_event(event, &options).unwrap();
+        assert_eq!(
+            String::from_utf8(frame).unwrap(),
+            "vector.histogram=1.5|h|@0.5|#empty_tag:,normal_tag:value,true_tag\n"
+        );
+    }
+
+    #[test]
+    fn test_encode_namespace_tag() {
+        let mut namespaced = tags();
+        namespaced.insert("__namespace".to_owned(), "app".to_owned());
+        let counter = |tags| {
+            Event::Metric(Metric {
+                name: "counter".to_owned(),
+                timestamp: None,
+                tags,
+                kind: MetricKind::Incremental,
+                value: MetricValue::Counter { value: 1.0 },
+            })
+        };
+        let encode = |event| {
+            let options = EncodingOptions {
+                namespace: Some("vector".into()),
+                namespace_tag: Some("__namespace".into()),
+                ..Default::default()
+            };
+            let frame = encode_event(event, &options);
+            String::from_utf8(frame.unwrap()).unwrap()
+        };
+
+        assert_eq!(
+            encode(counter(Some(namespaced))),
+            "app.counter:1|c|#empty_tag:,normal_tag:value,true_tag\n"
+        );
+        assert_eq!(
+            encode(counter(Some(tags()))),
+            "vector.counter:1|c|#empty_tag:,normal_tag:value,true_tag\n"
+        );
+
+        let mut only_namespace = BTreeMap::new();
+        only_namespace.insert("__namespace".to_owned(), "app".to_owned());
+        assert_eq!(encode(counter(Some(only_namespace))), "app.counter:1|c\n");
+    }
+
+    #[test]
+    fn test_encode_empty_name() {
+        let counter = || {
+            Event::Metric(Metric {
+                name: String::new(),
+                timestamp: None,
+                tags: None,
+                kind: MetricKind::Incremental,
+                value: MetricValue::Counter { value: 5.0 },
+            })
+        };
+        let options = EncodingOptions {
+            namespace: Some("vector".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(encode_event(counter(), &options), None);
+
+        let options = EncodingOptions {
+            empty_name_placeholder: Some("unnamed".to_owned()),
+            ..options
+        };
+        assert_eq!(
+            encode_event(counter(), &options),
+            Some(b"vector.unnamed:5|c\n".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_encode_namespace_separator() {
+        let counter = |tags: Vec<(&str, &str)>| {
+            Event::Metric(Metric {
+                name: "counter".to_owned(),
+                timestamp: None,
+                tags: if tags.is_empty() {
+                    None
+                } else {
+                    Some(
+                        tags.into_iter()
+                            .map(|(name, value)| (name.to_owned(), value.to_owned()))
+                            .collect(),
+                    )
+                },
+                kind: MetricKind::Incremental,
+                value: MetricValue::Counter { value: 1.0 },
+            })
+        };
+        let encode = |event, namespace: &str, separator: &str| {
+            let options = EncodingOptions {
+                namespace: Some(namespace.to_owned()),
+                namespace_separator: separator.to_owned(),
+                ..Default::default()
+            };
+            String::from_utf8(encode_event(event, &options).unwrap()).unwrap()
+        };
+
+        assert_eq!(
+            encode(counter(vec![]), "vector", &default_namespace_separator()),
+            "vector.counter:1|c\n"
+        );
+        assert_eq!(
+            encode(counter(vec![]), "vector", "_"),
+            "vector_counter:1|c\n"
+        );
+        assert_eq!(encode(counter(vec![]), "", "_"), "counter:1|c\n");
+        assert_eq!(
+            encode(
+                counter(vec![("__no_namespace", "true"), ("env", "prod")]),
+                "vector",
+                "_"
+            ),
+            "counter:1|c|#env:prod\n"
+        );
+    }
+
+    #[test]
+    fn test_encode_suppresses_zero_counters() {
+        let _ = crate::metrics::init();
+        let counter = |value, kind| {
+            Event::Metric(Metric {
+                name: "counter".to_owned(),
+                timestamp: None,
+                tags: None,
+                kind,
+                value: MetricValue::Counter { value },
+            })
+        };
+        let options = EncodingOptions {
+            suppress_zero_counters: true,
+            ..Default::default()
+        };
+
+        let suppressed = counter_value("events_suppressed_total");
+        assert_eq!(
+            encode_event(counter(0.0, MetricKind::Incremental), &options),
+            None
+        );
+        assert!(counter_value("events_suppressed_total") >= suppressed + 1.0);
+
+        assert_eq!(
+            encode_event(counter(1.0, MetricKind::Incremental), &options),
+            Some(b"counter:1|c\n".to_vec())
+        );
+        assert_eq!(
+            encode_event(counter(0.0, MetricKind::Incremental), &Default::default()),
+            Some(b"counter:0|c\n".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_encode_name_rewrites() {
+        let event = Event::Metric(Metric {
+            name: "api.user.123.latency".to_owned(),
+            timestamp: None,
+            tags: None,
+            kind: MetricKind::Absolute,
+            value: MetricValue::Gauge { value: 1.5 },
+        });
+        let rewrites = vec![NameRewrite {
+            pattern: r"\.\d+\.".into(),
+            replacement: ".".into(),
+        }];
+        let options = EncodingOptions {
+            namespace: Some("vector".into()),
+            name_rewrites: compile_name_rewrites(&rewrites).unwrap(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            encode_event(event, &options),
+            Some(b"vector.api.user.latency:1.5|g\n".to_vec())
+        );
+
+        let invalid = vec![NameRewrite {
+            pattern: "(".into(),
+            replacement: String::new(),
+        }];
+        assert!(compile_name_rewrites(&invalid).is_err());
+    }
+
+    #[test]
+    fn test_encode_set_count() {
+        let event = Event::Metric(Metric {
+            name: "users".to_owned(),
+            timestamp: None,
+            tags: None,
+            kind: MetricKind::Incremental,
+            value: MetricValue::Set {
+                values: vec!["alice".to_owned(), "bob".to_owned(), "carol".to_owned()]
+                    .into_iter()
+                    .collect(),
+            },
+        });
+        let options = EncodingOptions {
+            set_emission: SetEmission::Count,
+            ..Default::default()
+        };
+
+        assert_eq!(encode_event(event, &options), Some(b"users:3|g\n".to_vec()));
+    }
+
+    #[test]
+    fn test_encode_tag_prefix() {
+        let event = Event::Metric(Metric {
+            name: "counter".to_owned(),
+            timestamp: None,
+            tags: Some(
+                vec![("env".to_owned(), "prod".to_owned())]
+                    .into_iter()
+                    .collect(),
+            ),
+            kind: MetricKind::Incremental,
+            value: MetricValue::Counter { value: 1.0 },
+        });
+        let options = EncodingOptions {
+            tag_prefix: "T".to_owned(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            encode_event(event, &options),
+            Some(b"counter:1|c|Tenv:prod\n".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_encode_entity_id() {
+        let metric = |tags: Vec<(&str, &str)>| {
+            Event::Metric(Metric {
+                name: "counter".to_owned(),
+                timestamp: None,
+                tags: Some(
+                    tags.into_iter()
+                        .map(|(name, value)| (name.to_owned(), value.to_owned()))
+                        .collect(),
+                ),
+                kind: MetricKind::Incremental,
+                value: MetricValue::Counter { value: 1.0 },
+            })
+        };
+        let options = EncodingOptions {
+            entity_id_tag: Some("container_id".to_owned()),
+            dialect: Dialect::Dogstatsd,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            encode_event(
+                metric(vec![("container_id", "abc123"), ("env", "prod")]),
+                &options
+            ),
+            Some(b"counter:1|c|#env:prod|e:abc123\n".to_vec())
+        );
+        assert_eq!(
+            encode_event(metric(vec![("env", "prod")]), &options),
+            Some(b"counter:1|c|#env:prod\n".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_encode_summary() {
+        let event = Event::Metric(Metric {
+            name: "latency".to_owned(),
+            timestamp: None,
+            tags: None,
+            kind: MetricKind::Absolute,
+            value: MetricValue::AggregatedSummary {
+                quantiles: vec![0.5, 0.999],
+                values: vec![1.5, 4.0],
+                count: 6,
+                sum: 12.5,
+            },
+        });
+        let frame = encode_event(event, &Default::default()).unwrap();
+        assert_eq!(
+            String::from_utf8(frame).unwrap(),
+            "latency.q50:1.5|g\nlatency.q99.9:4|g\nlatency.count:6|g\nlatency.sum:12.5|g\n"
+        );
+    }
+
+    #[test]
+    fn test_encode_histogram() {
+        let event = Event::Metric(Metric {
+            name: "latency".to_owned(),
+            timestamp: None,
+            tags: None,
+            kind: MetricKind::Incremental,
+            value: MetricValue::AggregatedHistogram {
+                buckets: vec![1.0, 2.0, 4.0],
+                counts: vec![1, 3, 3],
+                count: 5,
+                sum: 10.0,
+            },
+        });
+        let frame = encode_event(event, &Default::default()).unwrap();
+        assert_eq!(
+            String::from_utf8(frame).unwrap(),
+            "latency:1|h\nlatency:2|h|@0.5\nlatency:4|h|@0.5\n"
+        );
+    }
+
+    #[test]
+    fn estimated_size_is_an_upper_bound() {
+        let metric = |value| Metric {
+            name: "vector.http.requests".to_owned(),
+            timestamp: None,
+            tags: Some({
+                let mut tags = tags();
+                tags.insert("container".to_owned(), "4f2a9c1e".to_owned());
+                tags
+            }),
+            kind: MetricKind::Incremental,
+            value,
+        };
+        let metrics = vec![
+            metric(MetricValue::Counter { value: 0.1 + 0.2 }),
+            metric(MetricValue::Gauge { value: -1.5e-7 }),
+            metric(MetricValue::Set {
+                values: vec!["alice".to_owned(), "bob".to_owned()]
+                    .into_iter()
+                    .collect(),
+            }),
+            metric(MetricValue::Distribution {
+                values: vec![1.0, 22.5, 333.25],
+                sample_rates: vec![1, 3, 7],
+                statistic: StatisticKind::Summary,
+            }),
+            metric(MetricValue::AggregatedHistogram {
+                buckets: vec![0.005, 0.25, 10.0],
+                counts: vec![1, 4, 9],
+                count: 12,
+                sum: 30.0,
+            }),
+            metric(MetricValue::AggregatedSummary {
+                quantiles: vec![0.5, 0.9, 0.999],
+                values: vec![1.25, 8.0, 120.5],
+                count: 300,
+                sum: 1234.5,
+            }),
+        ];
+        let options = vec![
+            EncodingOptions::default(),
+            EncodingOptions {
+                namespace: Some("production.web".to_owned()),
+                namespace_separator: "::".to_owned(),
+                ..Default::default()
+            },
+            EncodingOptions {
+                value_delimiter: '→',
+                tag_prefix: "#tags=".to_owned(),
+                ..Default::default()
+            },
+            EncodingOptions {
+                namespace: Some("production.web".to_owned()),
+                entity_id_tag: Some("container".to_owned()),
+                dialect: Dialect::Dogstatsd,
+                value_delimiter: '→',
+                tag_prefix: "#tags=".to_owned(),
+                ..Default::default()
+            },
+        ];
+
+        for options in &options {
+            for metric in metrics.clone() {
+                let frames = match metric.value {
+                    MetricValue::AggregatedSummary { .. }
+                    | MetricValue::AggregatedHistogram { .. } => {
+                        expand_summary(metric, &options.summary_quantile_name)
+                            .into_iter()
+                            .flat_map(expand_histogram)
+                            .collect()
+                    }
+                    _ => vec![metric],
+                };
+                for metric in frames {
+                    // The sink estimates once it has taken the entity id
+                    // out of the tags.
+                    let mut estimated = metric.clone();
+                    let entity_id = options
+                        .entity_id_tag
+                        .as_deref()
+                        .and_then(|tag| take_tag(&mut estimated.tags, tag));
+                    let estimate = estimated_size(
+                        &estimated,
+                        options.namespace.as_deref(),
+                        entity_id.as_deref(),
+                        options,
+                    );
+                    let frame = encode_event(Event::Metric(metric), options).unwrap();
+                    assert!(
+                        frame.len() <= estimate,
+                        "{} > {} for {:?}",
+                        frame.len(),
+                        estimate,
+                        String::from_utf8(frame)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn suppresses_unchanged_gauges() {
+        let gauge = |value, env: &str| {
+            Event::Metric(Metric {
+                name: "temperature".to_owned(),
+                timestamp: None,
+                tags: Some(
+                    vec![("env".to_owned(), env.to_owned())]
+                        .into_iter()
+                        .collect(),
+                ),
+                kind: MetricKind::Absolute,
+                value: MetricValue::Gauge { value },
+            })
+        };
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut gauges = UnchangedGauges::new(Duration::from_secs(10));
+
+        // Three identical values are sent once.
+        assert!(gauges.filter_at(gauge(20.0, "prod"), at(0)).is_some());
+        assert!(gauges.filter_at(gauge(20.0, "prod"), at(1)).is_none());
+        assert!(gauges.filter_at(gauge(20.0, "prod"), at(2)).is_none());
+        // Series are tracked apart.
+        assert!(gauges.filter_at(gauge(20.0, "dev"), at(2)).is_some());
+        // The value is sent again once the interval has passed.
+        assert!(gauges.filter_at(gauge(20.0, "prod"), at(10)).is_some());
+        assert!(gauges.filter_at(gauge(20.0, "prod"), at(11)).is_none());
+        // A changed value is sent right away.
+        assert!(gauges.filter_at(gauge(21.0, "prod"), at(12)).is_some());
+
+        // Incremental gauges are adjustments, so are always sent.
+        let mut adjustment = gauge(0.0, "prod").into_metric();
+        adjustment.kind = MetricKind::Incremental;
+        let adjustment = Event::Metric(adjustment);
+        assert!(gauges.filter_at(adjustment.clone(), at(13)).is_some());
+        assert!(gauges.filter_at(adjustment, at(14)).is_some());
+    }
+
+    #[test]
+    fn forgets_gauges_sent_before_the_interval() {
+        let gauge = |name: String| {
+            Event::Metric(Metric {
+                name,
+                timestamp: None,
+                tags: None,
+                kind: MetricKind::Absolute,
+                value: MetricValue::Gauge { value: 1.0 },
+            })
+        };
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut gauges = UnchangedGauges::new(Duration::from_secs(10));
+
+        // Every series is new, as its tags would be under high churn.
+        for i in 0..100 {
+            assert!(gauges.filter_at(gauge(format!("g{}", i)), at(0)).is_some());
+        }
+        assert_eq!(gauges.last_sent.len(), 100);
+
+        assert!(gauges.filter_at(gauge("g100".to_owned()), at(10)).is_some());
+        assert_eq!(gauges.last_sent.len(), 1);
+    }
+
+    #[test]
+    fn converts_absolute_counters_to_deltas() {
+        let counter = |value, env: &str| {
+            Event::Metric(Metric {
+                name: "requests".to_owned(),
+                timestamp: None,
+                tags: Some(
+                    vec![("env".to_owned(), env.to_owned())]
+                        .into_iter()
+                        .collect(),
+                ),
+                kind: MetricKind::Absolute,
+                value: MetricValue::Counter { value },
+            })
+        };
+        let delta = |event: Option<Event>| {
+            event.map(|event| {
+                let metric = event.into_metric();
+                assert_eq!(metric.kind, MetricKind::Incremental);
+                metric.value
+            })
+        };
+        let mut deltas = AbsoluteDeltas::new(DELTA_SERIES_TTL);
+
+        assert_eq!(deltas.to_incremental(counter(10.0, "prod")), None);
+        assert_eq!(deltas.to_incremental(counter(1.0, "dev")), None);
+        assert_eq!(
+            delta(deltas.to_incremental(counter(15.0, "prod"))),
+            Some(MetricValue::Counter { value: 5.0 })
+        );
+        // The counter was reset.
+        assert_eq!(
+            delta(deltas.to_incremental(counter(3.0, "prod"))),
+            Some(MetricValue::Counter { value: 3.0 })
+        );
+        assert_eq!(
+            delta(deltas.to_incremental(counter(2.0, "dev"))),
+            Some(MetricValue::Counter { value: 1.0 })
+        );
+    }
+
+    #[test]
+    fn converts_absolute_histograms_to_deltas() {
+        let histogram = |counts: Vec<u32>, count, sum| {
+            Event::Metric(Metric {
+                name: "latency".to_owned(),
+                timestamp: None,
+                tags: None,
+                kind: MetricKind::Absolute,
+                value: MetricValue::AggregatedHistogram {
+                    buckets: vec![1.0, 2.0],
+                    counts,
+                    count,
+                    sum,
+                },
+            })
+        };
+        let mut deltas = AbsoluteDeltas::new(DELTA_SERIES_TTL);
+
+        assert_eq!(deltas.to_incremental(histogram(vec![1, 2], 3, 4.0)), None);
+        let metric = deltas
+            .to_incremental(histogram(vec![2, 4], 5, 7.5))
+            .unwrap()
+            .into_metric();
+        assert_eq!(metric.kind, MetricKind::Incremental);
+        assert_eq!(
+            metric.value,
+            MetricValue::AggregatedHistogram {
+                buckets: vec![1.0, 2.0],
+                counts: vec![1, 2],
+                count: 2,
+                sum: 3.5,
+            }
+        );
+    }
+
+    #[test]
+    fn converts_absolute_summaries_to_deltas() {
+        let summary = |values: Vec<f64>, count, sum| {
+            Event::Metric(Metric {
+                name: "latency".to_owned(),
+                timestamp: None,
+                tags: None,
+                kind: MetricKind::Absolute,
+                value: MetricValue::AggregatedSummary {
+                    quantiles: vec![0.5, 0.99],
+                    values,
+                    count,
+                    sum,
+                },
+            })
+        };
+        let mut deltas = AbsoluteDeltas::new(DELTA_SERIES_TTL);
+
+        assert_eq!(
+            deltas.to_incremental(summary(vec![1.0, 3.0], 10, 15.0)),
+            None
+        );
+        let metric = deltas
+            .to_incremental(summary(vec![2.0, 5.0], 14, 25.0))
+            .unwrap()
+            .into_metric();
+        assert_eq!(metric.kind, MetricKind::Incremental);
+        assert_eq!(
+            metric.value,
+            MetricValue::AggregatedSummary {
+                quantiles: vec![0.5, 0.99],
+                values: vec![2.0, 5.0],
+                count: 4,
+                sum: 10.0,
+            }
+        );
+    }
+
+    #[test]
+    fn expires_absolute_delta_baselines() {
+        let counter = |name: &str, value| {
+            Event::Metric(Metric {
+                name: name.to_owned(),
+                timestamp: None,
+                tags: None,
+                kind: MetricKind::Absolute,
+                value: MetricValue::Counter { value },
+            })
+        };
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut deltas = AbsoluteDeltas::new(Duration::from_secs(10));
+
+        for i in 0..100 {
+            let name = format!("requests{}", i);
+            assert!(deltas
+                .to_incremental_at(counter(&name, 1.0), at(0))
+                .is_none());
+        }
+        assert!(deltas
+            .to_incremental_at(counter("requests0", 2.0), at(5))
+            .is_some());
+        assert_eq!(deltas.previous.len(), 100);
+
+        // Series not seen within the TTL are forgotten, and start over.
+        assert!(deltas
+            .to_incremental_at(counter("requests1", 3.0), at(12))
+            .is_none());
+        assert_eq!(deltas.previous.len(), 2);
+        assert!(deltas
+            .to_incremental_at(counter("requests0", 4.0), at(14))
+            .is_some());
+    }
+
+    #[test]
+    fn rejects_absolute_histograms_without_deltas() {
+        let event = Event::Metric(Metric {
+            name: "latency".to_owned(),
+            timestamp: None,
+            tags: None,
+            kind: MetricKind::Absolute,
+            value: MetricValue::AggregatedHistogram {
+                buckets: vec![1.0, 2.0],
+                counts: vec![1, 2],
+                count: 3,
+                sum: 4.0,
+            },
+        });
+        assert_eq!(encode_event(event, &Default::default()), None);
+    }
+
+    #[cfg(feature = "sources-statsd")]
+    #[test]
+    fn test_encode_gauge() {
+        let metric1 = Metric {
+            name: "gauge".to_owned(),
+            timestamp: None,
+            tags: Some(tags()),
+            kind: MetricKind::Incremental,
+            value: MetricValue::Gauge { value: -1.5 },
+        };
+        let event = Event::Metric(metric1.clone());
+        let frame = &encode_event(event, &Default::default()).unwrap();
+        let metric2 = parse(from_utf8(&frame).unwrap().trim()).unwrap();
+        assert_eq!(metric1, metric2);
+    }
+
+    #[cfg(feature = "sources-statsd")]
+    #[test]
+    fn test_encode_absolute_gauge() {
+        let metric1 = Metric {
+            name: "gauge".to_owned(),
+            timestamp: None,
+            tags: Some(tags()),
+            kind: MetricKind::Absolute,
+            value: MetricValue::Gauge { value: 1.5 },
+        };
+        let event = Event::Metric(metric1.clone());
+        let frame = &encode_event(event, &Default::default()).unwrap();
+        let metric2 = parse(from_utf8(&frame).unwrap().trim()).unwrap();
+        assert_eq!(metric1, metric2);
+    }
+
+    #[cfg(feature = "sources-statsd")]
+    #[test]
+    fn test_encode_distribution() {
+        let metric1 = Metric {
+            name: "distribution".to_owned(),
+            timestamp: None,
+            tags: Some(tags()),
+            kind: MetricKind::Incremental,
+            value: MetricValue::Distribution {
+                values: vec![1.5],
+                sample_rates: vec![1],
+                statistic: StatisticKind::Histogram,
+            },
+        };
+        let event = Event::Metric(metric1.clone());
+        let frame = &encode_event(event, &Default::default()).unwrap();
+        let metric2 = parse(from_utf8(&frame).unwrap().trim()).unwrap();
+        assert_eq!(metric1, metric2);
+    }
+
+    #[cfg(feature = "sources-statsd")]
+    #[test]
+    fn test_encode_set() {
+        let metric1 = Metric {
+            name: "set".to_owned(),
+            timestamp: None,
+            tags: Some(tags()),
+            kind: MetricKind::Incremental,
+            value: MetricValue::Set {
+                values: vec!["abc".to_owned()].into_iter().collect(),
+            },
+        };
+        let event = Event::Metric(metric1.clone());
+        let frame = &encode_event(event, &Default::default()).unwrap();
+        let metric2 = parse(from_utf8(&frame).unwrap().trim()).unwrap();
+        assert_eq!(metric1, metric2);
+    }
+
+    #[cfg(feature = "sources-statsd")]
+    #[tokio::test]
+    async fn test_send_to_statsd() {
+        trace_init();
+
+        let addr = next_addr();
+
+        let config = StatsdSinkConfig {
+            namespace: Some("vector".into()),
+            batch: BatchConfig {
+                max_bytes: Some(512),
+                timeout_secs: Some(1),
+                ..Default::default()
+            },
+            ..test_config(Mode::Udp(UdpSinkConfig::new(addr.to_string())))
+        };
+
+        let context = SinkContext::new_test();
+        let (sink, _healthcheck) = config.build(context).await.unwrap();
+
+        let events = vec![
+            Event::Metric(Metric {
+                name: "counter".to_owned(),
+                timestamp: None,
+                tags: Some(tags()),
+                kind: MetricKind::Incremental,
+                value: MetricValue::Counter { value: 1.5 },
+            }),
+            Event::Metric(Metric {
+                name: "histogram".to_owned(),
+                timestamp: None,
+                tags: None,
+                kind: MetricKind::Incremental,
+                value: MetricValue::Distribution {
+                    values: vec![2.0],
+                    sample_rates: vec![100],
+                    statistic: StatisticKind::Histogram,
+                },
+            }),
+        ];
+        let server = MockStatsdServer::udp(addr).await;
+
+        sink.run(stream::iter(events)).await.unwrap();
+
+        assert_eq!(
+            server.wait_for_lines(2).await,
+            vec![
+                "vector.counter:1.5|c|#empty_tag:,normal_tag:value,true_tag",
+                "vector.histogram:2|h|@0.01",
+            ],
+        );
+    }
+
+    #[tokio::test]
+    async fn sends_to_memory() {
+        let memory = MemoryConfig::new(2);
+        let config = StatsdSinkConfig {
+            batch: BatchConfig {
+                max_events: Some(1),
+                ..Default::default()
+            },
+            ..test_config(Mode::Memory(memory.clone()))
+        };
+        let (sink, healthcheck) = config.build(SinkContext::new_test()).await.unwrap();
+        healthcheck.await.unwrap();
+
+        let events = (1..=3).map(|value| {
+            Event::Metric(Metric {
+                name: "counter".to_owned(),
+                timestamp: None,
+                tags: None,
+                kind: MetricKind::Incremental,
+                value: MetricValue::Counter {
+                    value: f64::from(value),
+                },
+            })
+        });
+        sink.run(stream::iter(events)).await.unwrap();
+
+        // Only the two most recent frames are kept.
+        assert_eq!(
+            memory.frames(),
+            vec![b"counter:2|c\n".to_vec(), b"counter:3|c\n".to_vec()]
+        );
+    }
+
+    #[tokio::test]
+    async fn limits_events_per_packet() {
+        let memory = MemoryConfig::new(10);
+        let config = StatsdSinkConfig {
+            batch: BatchConfig {
+                max_events: Some(3),
+                ..Default::default()
+            },
+            ..test_config(Mode::Memory(memory.clone()))
+        };
+        let (sink, _) = config.build(SinkContext::new_test()).await.unwrap();
+
+        // Every line is far below the default byte limit.
+        let events = (0..10).map(|_| {
+            Event::Metric(Metric {
+                name: "c".to_owned(),
+                timestamp: None,
+                tags: None,
+                kind: MetricKind::Incremental,
+                value: MetricValue::Counter { value: 1.0 },
+            })
+        });
+        sink.run(stream::iter(events)).await.unwrap();
+
+        let lines = memory
+            .frames()
+            .iter()
+            .map(|frame| frame.iter().filter(|byte| **byte == b'\n').count())
+            .collect::<Vec<_>>();
+        assert!(lines.iter().all(|lines| *lines <= 3), "{:?}", lines);
+        assert_eq!(lines.iter().sum::<usize>(), 10);
+    }
+
+    #[tokio::test]
+    async fn sends_summary_count_deltas() {
+        let memory = MemoryConfig::new(10);
+        let config = StatsdSinkConfig {
+            absolute_to_incremental: true,
+            ..test_config(Mode::Memory(memory.clone()))
+        };
+        let (sink, _) = config.build(SinkContext::new_test()).await.unwrap();
+
+        let events = vec![(10, 15.0), (14, 25.0)]
+            .into_iter()
+            .map(|(count, sum)| {
+                Event::Metric(Metric {
+                    name: "latency".to_owned(),
+                    timestamp: None,
+                    tags: None,
+                    kind: MetricKind::Absolute,
+                    value: MetricValue::AggregatedSummary {
+                        quantiles: vec![0.5],
+                        values: vec![1.5],
+                        count,
+                        sum,
+                    },
+                })
+            });
+        sink.run(stream::iter(events)).await.unwrap();
+
+        // The first summary only sets the baseline.
+        assert_eq!(
+            memory.frames(),
+            vec![b"latency.q50:1.5|g\nlatency.count:4|c\nlatency.sum:10|c\n".to_vec()]
+        );
+    }
+
+    #[tokio::test]
+    async fn paces_packets_per_flush() {
+        let memory = MemoryConfig::new(10);
+        let config = StatsdSinkConfig {
+            batch: BatchConfig {
+                max_events: Some(1),
+                ..Default::default()
+            },
+            max_packets_per_flush: Some(4),
+            ..test_config(Mode::Memory(memory.clone()))
+        };
+        let (sink, _) = config.build(SinkContext::new_test()).await.unwrap();
+
+        // Each metric fills a packet, so these are more packets than fit in
+        // a one second flush at four packets per flush.
+        let events = (1..=6).map(|value| {
+            Event::Metric(Metric {
+                name: "counter".to_owned(),
+                timestamp: None,
+                tags: None,
+                kind: MetricKind::Incremental,
+                value: MetricValue::Counter {
+                    value: f64::from(value),
+                },
+            })
+        });
+        let started = Instant::now();
+        let sent = tokio::spawn(sink.run(stream::iter(events)));
+
+        delay_for(Duration::from_millis(100)).await;
+        let burst = memory.frames().len();
+        assert!(burst < 6, "{}", burst);
+
+        sent.await.unwrap().unwrap();
+        // Packets are sent 250ms apart.
+        assert!(started.elapsed() >= Duration::from_millis(1250));
+        assert_eq!(memory.frames().len(), 6);
     }
 
-    #[test]
-    fn test_encode_tags() {
+    #[cfg(feature = "sources-statsd")]
+    #[tokio::test]
+    async fn sorts_lines_by_name() {
+        let addr = next_addr();
+        let server = MockStatsdServer::udp(addr).await;
+        let config = StatsdSinkConfig {
+            sort_by_name: true,
+            ..test_config(Mode::Udp(UdpSinkConfig::new(addr.to_string())))
+        };
+        let (sink, _healthcheck) = config.build(SinkContext::new_test()).await.unwrap();
+
+        let events = vec!["zeta", "alpha", "mu"]
+            .into_iter()
+            .map(|name| {
+                Event::Metric(Metric {
+                    name: name.to_owned(),
+                    timestamp: None,
+                    tags: None,
+                    kind: MetricKind::Incremental,
+                    value: MetricValue::Counter { value: 1.0 },
+                })
+            })
+            .collect::<Vec<_>>();
+        sink.run(stream::iter(events)).await.unwrap();
+
         assert_eq!(
-            &encode_tags(&tags()),
-            "empty_tag:,normal_tag:value,true_tag"
+            server.wait_for_lines(3).await,
+            vec!["alpha:1|c", "mu:1|c", "zeta:1|c"]
         );
     }
 
     #[cfg(feature = "sources-statsd")]
-    #[test]
-    fn test_encode_counter() {
-        let metric1 = Metric {
-            name: "counter".to_owned(),
-            timestamp: None,
-            tags: Some(tags()),
-            kind: MetricKind::Incremental,
-            value: MetricValue::Counter { value: 1.5 },
+    #[tokio::test]
+    async fn orders_lines_by_type() {
+        let addr = next_addr();
+        let server = MockStatsdServer::udp(addr).await;
+        let config = StatsdSinkConfig {
+            sort_by_type: true,
+            ..test_config(Mode::Udp(UdpSinkConfig::new(addr.to_string())))
         };
-        let event = Event::Metric(metric1.clone());
-        let frame = &encode_event(event, None).unwrap();
-        let metric2 = parse(from_utf8(&frame).unwrap().trim()).unwrap();
-        assert_eq!(metric1, metric2);
+        let (sink, _healthcheck) = config.build(SinkContext::new_test()).await.unwrap();
+
+        let events = vec![
+            (
+                "set",
+                MetricValue::Set {
+                    values: vec!["a".to_owned()].into_iter().collect(),
+                },
+            ),
+            (
+                "dist",
+                MetricValue::Distribution {
+                    values: vec![1.0],
+                    sample_rates: vec![1],
+                    statistic: StatisticKind::Summary,
+                },
+            ),
+            ("gauge", MetricValue::Gauge { value: 2.0 }),
+            ("counter", MetricValue::Counter { value: 1.0 }),
+        ]
+        .into_iter()
+        .map(|(name, value)| {
+            Event::Metric(Metric {
+                name: name.to_owned(),
+                timestamp: None,
+                tags: None,
+                kind: MetricKind::Incremental,
+                value,
+            })
+        })
+        .collect::<Vec<_>>();
+        sink.run(stream::iter(events)).await.unwrap();
+
+        assert_eq!(
+            server.wait_for_lines(4).await,
+            vec!["counter:1|c", "gauge:2|g", "dist:1|d", "set:a|s"]
+        );
     }
 
     #[cfg(feature = "sources-statsd")]
-    #[test]
-    fn test_encode_absolute_counter() {
-        let metric1 = Metric {
-            name: "counter".to_owned(),
-            timestamp: None,
-            tags: None,
-            kind: MetricKind::Absolute,
-            value: MetricValue::Counter { value: 1.5 },
+    #[tokio::test]
+    async fn sends_each_metric_immediately() {
+        let addr = next_addr();
+        let server = MockStatsdServer::udp(addr).await;
+        let config = StatsdSinkConfig {
+            batch: BatchConfig {
+                timeout_secs: Some(60),
+                ..Default::default()
+            },
+            batch_disabled: true,
+            ..test_config(Mode::Udp(UdpSinkConfig::new(addr.to_string())))
         };
-        let event = Event::Metric(metric1);
-        let frame = &encode_event(event, None).unwrap();
-        // The statsd parser will parse the counter as Incremental,
-        // so we can't compare it with the parsed value.
-        assert_eq!("counter:1.5|c\n", from_utf8(&frame).unwrap());
+        let (sink, _healthcheck) = config.build(SinkContext::new_test()).await.unwrap();
+
+        let (tx, rx) = futures::channel::mpsc::unbounded();
+        tokio::spawn(sink.run(rx));
+
+        for value in 1..=3 {
+            tx.unbounded_send(Event::Metric(Metric {
+                name: "counter".to_owned(),
+                timestamp: None,
+                tags: None,
+                kind: MetricKind::Incremental,
+                value: MetricValue::Counter {
+                    value: f64::from(value),
+                },
+            }))
+            .unwrap();
+
+            // Far sooner than the batch timeout.
+            let lines = server.wait_for_lines(value as usize).await;
+            assert_eq!(lines.last().unwrap(), &format!("counter:{}|c", value));
+        }
+    }
+
+    #[test]
+    fn sort_by_name_is_stable() {
+        let frame = b"foo.bar:1|c\nfoo:2|g\nfoo:1|c\n".to_vec();
+        assert_eq!(
+            sort_by_name(frame, ':'),
+            b"foo:2|g\nfoo:1|c\nfoo.bar:1|c\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn sorts_lines_by_type() {
+        let frame = b"set:a|s\ngauge:1|g\ndist:1|d\ncounter:1|c\ntimer:1|ms\nother:1|c\n".to_vec();
+        assert_eq!(
+            sort_by_type(frame),
+            b"counter:1|c\nother:1|c\ngauge:1|g\ndist:1|d\ntimer:1|ms\nset:a|s\n".to_vec()
+        );
+    }
+
+    #[tokio::test]
+    async fn spilled_batches_roundtrip() {
+        let mut data = Vec::new();
+        encode_spilled(b"a:1|c\n", &mut data);
+        encode_spilled(b"b:2|g\nc:3|g\n", &mut data);
+        // A record cut short by a crash is dropped.
+        data.extend_from_slice(&[0, 0, 0, 10, b'd']);
+
+        let mut reader = &data[..];
+        let mut frames = Vec::new();
+        while let Some(frame) = read_spilled(&mut reader).await.unwrap() {
+            frames.push(frame);
+        }
+        assert_eq!(
+            frames,
+            vec![b"a:1|c\n".to_vec(), b"b:2|g\nc:3|g\n".to_vec()]
+        );
+    }
+
+    #[tokio::test]
+    async fn disk_fallback_drops_oldest_over_max_bytes() {
+        let path = tempfile::tempdir()
+            .unwrap()
+            .into_path()
+            .join("statsd.spill");
+        // Room for two of the 10 byte records.
+        let fallback = DiskFallback::new(path.clone(), 20);
+        for frame in &[b"a:1|c\n", b"b:1|c\n", b"c:1|c\n"] {
+            fallback.spill(&frame[..]).await.unwrap();
+        }
+        assert!(fallback.spill(&[0; 17]).await.is_err());
+
+        let data = tokio::fs::read(&path).await.unwrap();
+        let mut reader = &data[..];
+        let mut frames = Vec::new();
+        while let Some(frame) = read_spilled(&mut reader).await.unwrap() {
+            frames.push(frame);
+        }
+        assert_eq!(frames, vec![b"b:1|c\n".to_vec(), b"c:1|c\n".to_vec()]);
     }
 
     #[cfg(feature = "sources-statsd")]
+    #[tokio::test]
+    async fn disk_fallback_spills_and_replays() {
+        trace_init();
+
+        let addr = next_addr();
+        let path = tempfile::tempdir()
+            .unwrap()
+            .into_path()
+            .join("statsd.spill");
+        let fallback = Arc::new(DiskFallback::new(path.clone(), u64::max_value()));
+        let mode = Mode::Tcp(StatsdTcpConfig::new(addr.to_string()));
+
+        // Nothing is listening yet, so the batch is spilled to disk.
+        let (client, _) = build_client(&mode, SinkContext::new_test()).unwrap();
+        let mut service = StatsdSvc {
+            client,
+            fallback: Some(Arc::clone(&fallback)),
+            buffered: None,
+            sort_by_name: None,
+            sort_by_type: false,
+            pacing: None,
+        };
+        service.call(b"counter:1|c\n".to_vec()).await.unwrap();
+        assert!(std::fs::metadata(&path).unwrap().len() > 0);
+
+        let server = MockStatsdServer::tcp(addr).await;
+        let (client, _) = build_client(&mode, SinkContext::new_test()).unwrap();
+        let mut replay_service = StatsdSvc {
+            client,
+            fallback: None,
+            buffered: None,
+            sort_by_name: None,
+            sort_by_type: false,
+            pacing: None,
+        };
+        fallback.replay(&mut replay_service).await;
+
+        assert_eq!(server.wait_for_lines(1).await, vec!["counter:1|c"]);
+        assert!(std::fs::metadata(&path).is_err());
+        assert!(std::fs::metadata(&fallback.replay_path).is_err());
+    }
+
+    #[tokio::test]
+    async fn disk_fallback_keeps_unsent_batches() {
+        trace_init();
+
+        let addr = next_addr();
+        let path = tempfile::tempdir()
+            .unwrap()
+            .into_path()
+            .join("statsd.spill");
+        let fallback = DiskFallback::new(path.clone(), u64::max_value());
+        fallback.spill(b"a:1|c\n").await.unwrap();
+        fallback.spill(b"b:1|c\n").await.unwrap();
+
+        // Nothing is listening, so the replay fails and puts both back.
+        let mode = Mode::Tcp(StatsdTcpConfig::new(addr.to_string()));
+        let (client, _) = build_client(&mode, SinkContext::new_test()).unwrap();
+        let mut replay_service = StatsdSvc {
+            client,
+            fallback: None,
+            buffered: None,
+            sort_by_name: None,
+            sort_by_type: false,
+            pacing: None,
+        };
+        fallback.replay(&mut replay_service).await;
+        fallback.spill(b"c:1|c\n").await.unwrap();
+
+        let data = tokio::fs::read(&path).await.unwrap();
+        let mut reader = &data[..];
+        let mut frames = Vec::new();
+        while let Some(frame) = read_spilled(&mut reader).await.unwrap() {
+            frames.push(frame);
+        }
+        assert_eq!(
+            frames,
+            vec![
+                b"a:1|c\n".to_vec(),
+                b"b:1|c\n".to_vec(),
+                b"c:1|c\n".to_vec()
+            ]
+        );
+        assert!(std::fs::metadata(&fallback.replay_path).is_err());
+    }
+
+    fn counter_value(name: &str) -> f64 {
+        counter_value_where(name, |_| true)
+    }
+
+    fn counter_value_where(name: &str, filter: impl Fn(&Metric) -> bool) -> f64 {
+        let controller = crate::metrics::get_controller().unwrap();
+        crate::metrics::capture_metrics(controller)
+            .map(Event::into_metric)
+            .filter(|metric| metric.name == name && filter(metric))
+            .map(|metric| match metric.value {
+                MetricValue::Counter { value } => value,
+                _ => 0.0,
+            })
+            .sum()
+    }
+
     #[test]
-    fn test_encode_gauge() {
-        let metric1 = Metric {
-            name: "gauge".to_owned(),
-            timestamp: None,
-            tags: Some(tags()),
-            kind: MetricKind::Incremental,
-            value: MetricValue::Gauge { value: -1.5 },
+    fn skips_empty_metrics() {
+        let metric = |value| {
+            Event::Metric(Metric {
+                name: "empty".to_owned(),
+                timestamp: None,
+                tags: None,
+                kind: MetricKind::Incremental,
+                value,
+            })
         };
-        let event = Event::Metric(metric1.clone());
-        let frame = &encode_event(event, None).unwrap();
-        let metric2 = parse(from_utf8(&frame).unwrap().trim()).unwrap();
-        assert_eq!(metric1, metric2);
+        let set = metric(MetricValue::Set {
+            values: Default::default(),
+        });
+        let distribution = metric(MetricValue::Distribution {
+            values: Vec::new(),
+            sample_rates: Vec::new(),
+            statistic: StatisticKind::Histogram,
+        });
+
+        assert_eq!(encode_event(set, &Default::default()), None);
+        assert_eq!(encode_event(distribution, &Default::default()), None);
     }
 
-    #[cfg(feature = "sources-statsd")]
     #[test]
-    fn test_encode_absolute_gauge() {
-        let metric1 = Metric {
-            name: "gauge".to_owned(),
-            timestamp: None,
-            tags: Some(tags()),
-            kind: MetricKind::Absolute,
-            value: MetricValue::Gauge { value: 1.5 },
+    fn counts_invalid_metrics() {
+        let _ = crate::metrics::init();
+        let is_statsd = |metric: &Metric| {
+            metric.tag_matches("component_type", "statsd")
+                && metric.tag_matches("component_kind", "sink")
         };
-        let event = Event::Metric(metric1.clone());
-        let frame = &encode_event(event, None).unwrap();
-        let metric2 = parse(from_utf8(&frame).unwrap().trim()).unwrap();
-        assert_eq!(metric1, metric2);
+        let before = counter_value_where("encode_errors_total", is_statsd);
+
+        emit!(StatsdInvalidMetricReceived {
+            value: &MetricValue::Counter { value: 1.0 },
+            kind: &MetricKind::Incremental,
+        });
+
+        // Other tests may record metrics concurrently.
+        assert!(counter_value_where("encode_errors_total", is_statsd) >= before + 1.0);
+    }
+
+    #[tokio::test]
+    async fn reports_shutdown_drain() {
+        let _ = crate::metrics::init();
+
+        let memory = MemoryConfig::new(1);
+        let config = test_config(Mode::Memory(memory.clone()));
+        let (sink, _healthcheck) = config.build(SinkContext::new_test()).await.unwrap();
+
+        // The batch timeout is far off, so all three are still buffered when
+        // the input ends.
+        let events = (1..=3).map(|value| {
+            Event::Metric(Metric {
+                name: "counter".to_owned(),
+                timestamp: None,
+                tags: None,
+                kind: MetricKind::Incremental,
+                value: MetricValue::Counter {
+                    value: f64::from(value),
+                },
+            })
+        });
+        sink.run(stream::iter(events)).await.unwrap();
+        assert_eq!(
+            memory.frames(),
+            vec![b"counter:1|c\ncounter:2|c\ncounter:3|c\n".to_vec()]
+        );
+
+        let controller = crate::metrics::get_controller().unwrap();
+        let metrics = crate::metrics::capture_metrics(controller)
+            .map(Event::into_metric)
+            .collect::<Vec<_>>();
+        // Other sinks may be drained concurrently, so the exact count isn't
+        // checked.
+        assert!(metrics
+            .iter()
+            .any(|metric| metric.name == "shutdown_pending_events"
+                && matches!(metric.value, MetricValue::Gauge { value } if value > 0.0)));
+        assert!(metrics
+            .iter()
+            .any(|metric| metric.name == "shutdown_drain_duration_nanoseconds"));
     }
 
-    #[cfg(feature = "sources-statsd")]
     #[test]
-    fn test_encode_distribution() {
-        let metric1 = Metric {
-            name: "distribution".to_owned(),
-            timestamp: None,
-            tags: Some(tags()),
-            kind: MetricKind::Incremental,
-            value: MetricValue::Distribution {
-                values: vec![1.5],
-                sample_rates: vec![1],
-                statistic: StatisticKind::Histogram,
-            },
+    fn counts_encoded_metric_types() {
+        let _ = crate::metrics::init();
+
+        let values = vec![
+            ("counter", MetricValue::Counter { value: 1.0 }),
+            ("gauge", MetricValue::Gauge { value: 1.0 }),
+            (
+                "distribution",
+                MetricValue::Distribution {
+                    values: vec![1.0],
+                    sample_rates: vec![1],
+                    statistic: StatisticKind::Histogram,
+                },
+            ),
+            (
+                "set",
+                MetricValue::Set {
+                    values: vec!["a".to_owned()].into_iter().collect(),
+                },
+            ),
+        ];
+        for (metric_type, value) in values {
+            let encoded = || {
+                counter_value_where("metrics_encoded_total", |metric| {
+                    metric
+                        .tags
+                        .as_ref()
+                        .and_then(|tags| tags.get("metric_type"))
+                        == Some(&metric_type.to_owned())
+                })
+            };
+            let before = encoded();
+
+            let event = Event::Metric(Metric {
+                name: "metric".to_owned(),
+                timestamp: None,
+                tags: None,
+                kind: MetricKind::Absolute,
+                value,
+            });
+            assert!(encode_event(event, &Default::default()).is_some());
+
+            // Other tests may encode metrics concurrently.
+            assert!(encoded() >= before + 1.0, "{}", metric_type);
+        }
+    }
+
+    #[cfg(feature = "sources-statsd")]
+    #[tokio::test]
+    async fn counts_reconnects() {
+        trace_init();
+        let _ = crate::metrics::init();
+
+        let addr = next_addr();
+        let mode = Mode::Tcp(StatsdTcpConfig::new(addr.to_string()));
+        let (client, _) = build_client(&mode, SinkContext::new_test()).unwrap();
+        let mut service = StatsdSvc {
+            client,
+            fallback: None,
+            buffered: None,
+            sort_by_name: None,
+            sort_by_type: false,
+            pacing: None,
         };
-        let event = Event::Metric(metric1.clone());
-        let frame = &encode_event(event, None).unwrap();
-        let metric2 = parse(from_utf8(&frame).unwrap().trim()).unwrap();
-        assert_eq!(metric1, metric2);
+
+        // Nothing is listening yet, so no connection is opened.
+        assert!(service.call(b"counter:1|c\n".to_vec()).await.is_err());
+        let opened = counter_value("connections_opened_total");
+        let closed = counter_value("connections_closed_total");
+
+        let _server = MockStatsdServer::tcp(addr).await;
+        service.call(b"counter:1|c\n".to_vec()).await.unwrap();
+
+        assert!(counter_value("connections_opened_total") >= opened + 1.0);
+        assert!(counter_value("connections_closed_total") >= closed + 1.0);
     }
 
     #[cfg(feature = "sources-statsd")]
-    #[test]
-    fn test_encode_set() {
-        let metric1 = Metric {
-            name: "set".to_owned(),
-            timestamp: None,
-            tags: Some(tags()),
-            kind: MetricKind::Incremental,
-            value: MetricValue::Set {
-                values: vec!["abc".to_owned()].into_iter().collect(),
-            },
+    #[tokio::test]
+    async fn tracks_buffered_bytes() {
+        let addr = next_addr();
+        let _server = MockStatsdServer::udp(addr).await;
+        let mode = Mode::Udp(UdpSinkConfig::new(addr.to_string()));
+        let (client, _) = build_client(&mode, SinkContext::new_test()).unwrap();
+        let buffered = BufferedBytes::default();
+        let mut service = StatsdSvc {
+            client,
+            fallback: None,
+            buffered: Some(buffered.clone()),
+            sort_by_name: None,
+            sort_by_type: false,
+            pacing: None,
         };
-        let event = Event::Metric(metric1.clone());
-        let frame = &encode_event(event, None).unwrap();
-        let metric2 = parse(from_utf8(&frame).unwrap().trim()).unwrap();
-        assert_eq!(metric1, metric2);
+
+        let frame = b"counter:1|c\ngauge:2|g\n".to_vec();
+        buffered.add(12);
+        assert_eq!(buffered.get(), 12);
+        buffered.add(10);
+        assert_eq!(buffered.get(), frame.len());
+
+        service.call(frame).await.unwrap();
+        assert_eq!(buffered.get(), 0);
+
+        // What the overflow policies drop is never sent, so is removed too.
+        let mut drop_hook = buffered.drop_hook();
+        buffered.add(12);
+        buffered.add(22);
+        drop_hook(Dropped::Event(b"counter:1|c\n".to_vec()));
+        assert_eq!(buffered.get(), 22);
+        drop_hook(Dropped::Batch(b"counter:1|c\ngauge:2|g\n".to_vec()));
+        assert_eq!(buffered.get(), 0);
+
+        // Removing more than is buffered stops at zero instead of wrapping.
+        buffered.add(12);
+        buffered.remove(22);
+        assert_eq!(buffered.get(), 0);
     }
 
+    #[cfg(all(unix, feature = "sources-statsd"))]
     #[tokio::test]
-    async fn test_send_to_statsd() {
+    async fn falls_back_when_unix_socket_is_missing() {
         trace_init();
 
         let addr = next_addr();
+        let server = MockStatsdServer::tcp(addr).await;
+        let path = tempfile::tempdir()
+            .unwrap()
+            .into_path()
+            .join("missing.sock");
+        let config = StatsdSinkConfig {
+            fallback: Some(Box::new(Mode::Tcp(StatsdTcpConfig::new(addr.to_string())))),
+            ..test_config(Mode::Unix(UnixSinkConfig::new(path)))
+        };
+        let (sink, _healthcheck) = config.build(SinkContext::new_test()).await.unwrap();
+
+        let event = Event::Metric(Metric {
+            name: "counter".to_owned(),
+            timestamp: None,
+            tags: None,
+            kind: MetricKind::Incremental,
+            value: MetricValue::Counter { value: 1.0 },
+        });
+        sink.run(stream::iter(vec![event])).await.unwrap();
+
+        assert_eq!(server.wait_for_lines(1).await, vec!["counter:1|c"]);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn sends_to_fifo() {
+        use crate::sinks::util::fifo::test::{make_fifo, open_reader, read};
+
+        trace_init();
 
+        let path = make_fifo("statsd.fifo");
+        let mut reader = open_reader(&path);
         let config = StatsdSinkConfig {
             namespace: Some("vector".into()),
-            batch: BatchConfig {
-                max_bytes: Some(512),
-                timeout_secs: Some(1),
-                ..Default::default()
-            },
-            mode: Mode::Udp(UdpSinkConfig {
-                address: addr.to_string(),
-            }),
+            ..test_config(Mode::Fifo(FifoSinkConfig::new(path)))
         };
-
-        let context = SinkContext::new_test();
-        let (sink, _healthcheck) = config.build(context).await.unwrap();
+        let (sink, healthcheck) = config.build(SinkContext::new_test()).await.unwrap();
+        healthcheck.await.unwrap();
 
         let events = vec![
             Event::Metric(Metric {
                 name: "counter".to_owned(),
                 timestamp: None,
-                tags: Some(tags()),
+                tags: None,
                 kind: MetricKind::Incremental,
-                value: MetricValue::Counter { value: 1.5 },
+                value: MetricValue::Counter { value: 1.0 },
             }),
             Event::Metric(Metric {
-                name: "histogram".to_owned(),
+                name: "gauge".to_owned(),
                 timestamp: None,
                 tags: None,
-                kind: MetricKind::Incremental,
-                value: MetricValue::Distribution {
-                    values: vec![2.0],
-                    sample_rates: vec![100],
-                    statistic: StatisticKind::Histogram,
-                },
+                kind: MetricKind::Absolute,
+                value: MetricValue::Gauge { value: 2.0 },
             }),
         ];
-        let (tx, rx) = mpsc::channel(1);
-
-        let socket = UdpSocket::bind(addr).await.unwrap();
-        tokio::spawn(async move {
-            UdpFramed::new(socket, BytesCodec::new())
-                .map_err(|e| error!("Error reading line: {:?}", e))
-                .map_ok(|(bytes, _addr)| bytes.freeze())
-                .forward(
-                    tx.sink_compat()
-                        .sink_map_err(|e| error!("Error sending event: {:?}", e)),
-                )
-                .await
-                .unwrap()
-        });
-
         sink.run(stream::iter(events)).await.unwrap();
 
-        let messages = collect_n(rx, 1).await.unwrap();
-        assert_eq!(
-            messages[0],
-            Bytes::from("vector.counter:1.5|c|#empty_tag:,normal_tag:value,true_tag\nvector.histogram:2|h|@0.01\n"),
-        );
+        let expected = b"vector.counter:1|c\nvector.gauge:2|g\n";
+        assert_eq!(read(&mut reader, expected.len()).await, expected.to_vec());
     }
 }
@@ -189,6 +189,12 @@ pub trait Batch: Sized {
     fn finish(self) -> Self::Output;
     fn num_items(&self) -> usize;
 
+    /// How close the batch is to full, from `0.0` (empty) to `1.0` (full).
+    /// Batches that don't track their size report `0.0`.
+    fn fill_ratio(&self) -> f64 {
+        0.0
+    }
+
     /// Replace the current batch with a fresh one, returning the old one.
     fn fresh_replace(&mut self) -> Self
     where
@@ -267,4 +273,8 @@ where
     fn num_items(&self) -> usize {
         self.inner.num_items()
     }
+
+    fn fill_ratio(&self) -> f64 {
+        self.inner.fill_ratio()
+    }
 }
@@ -20,6 +20,7 @@ pub struct Buffer {
     num_items: usize,
     num_bytes: usize,
     settings: BatchSize<Self>,
+    soft_max_bytes: Option<usize>,
     compression: Compression,
 }
 
@@ -47,10 +48,18 @@ impl Buffer {
             num_items: 0,
             num_bytes: 0,
             settings,
+            soft_max_bytes: None,
             compression,
         }
     }
 
+    /// Reports the buffer as full once it holds `bytes`, so it is flushed
+    /// before reaching the hard byte limit.
+    pub fn with_soft_max_bytes(mut self, bytes: usize) -> Self {
+        self.soft_max_bytes = Some(bytes);
+        self
+    }
+
     pub fn push(&mut self, input: &[u8]) {
         self.num_items += 1;
         match &mut self.inner {
@@ -105,9 +114,8 @@ impl Batch for Buffer {
         } else {
             self.push(&item);
             self.num_bytes = new_bytes;
-            PushResult::Ok(
-                self.num_items >= self.settings.events || new_bytes >= self.settings.bytes,
-            )
+            let max_bytes = self.soft_max_bytes.unwrap_or(self.settings.bytes);
+            PushResult::Ok(self.num_items >= self.settings.events || new_bytes >= max_bytes)
         }
     }
 
@@ -116,7 +124,10 @@ impl Batch for Buffer {
     }
 
     fn fresh(&self) -> Self {
-        Self::new(self.settings, self.compression)
+        Self {
+            soft_max_bytes: self.soft_max_bytes,
+            ..Self::new(self.settings, self.compression)
+        }
     }
 
     fn finish(self) -> Self::Output {
@@ -131,13 +142,20 @@ impl Batch for Buffer {
     fn num_items(&self) -> usize {
         self.num_items
     }
+
+    fn fill_ratio(&self) -> f64 {
+        let max_bytes = self.soft_max_bytes.unwrap_or(self.settings.bytes);
+        let bytes = self.num_bytes as f64 / max_bytes.max(1) as f64;
+        let events = self.num_items as f64 / self.settings.events.max(1) as f64;
+        bytes.max(events).min(1.0)
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::{Buffer, Compression};
     use crate::buffers::Acker;
-    use crate::sinks::util::{BatchSettings, BatchSink};
+    use crate::sinks::util::{Batch, BatchSettings, BatchSink, PushResult};
     use futures::{compat::Future01CompatExt, future};
     use futures01::Sink;
     use std::{
@@ -204,4 +222,20 @@ mod test {
         .take(100_000)
         .flatten()));
     }
+
+    #[test]
+    fn soft_max_bytes_flushes_early() {
+        let batch_size = BatchSettings::default().bytes(100).events(1_000).size;
+        let mut buffer = Buffer::new(batch_size, Compression::None).with_soft_max_bytes(10);
+
+        assert!(matches!(
+            Batch::push(&mut buffer, b"123456".to_vec()),
+            PushResult::Ok(false)
+        ));
+        assert!(matches!(
+            Batch::push(&mut buffer, b"123456".to_vec()),
+            PushResult::Ok(true)
+        ));
+        assert_eq!(buffer.fresh().soft_max_bytes, Some(10));
+    }
 }
@@ -71,6 +71,12 @@ impl<T: EncodedLength> Batch for VecBuffer<T> {
     fn num_items(&self) -> usize {
         self.batch.len()
     }
+
+    fn fill_ratio(&self) -> f64 {
+        let bytes = self.bytes as f64 / self.settings.bytes.max(1) as f64;
+        let events = self.batch.len() as f64 / self.settings.events.max(1) as f64;
+        bytes.max(events).min(1.0)
+    }
 }
 
 impl EncodedLength for Bytes {
@@ -0,0 +1,328 @@
+//! Writes to a named pipe (FIFO). Writes only go anywhere while a reader
+//! has the pipe open, so frames written without one are either dropped or
+//! kept for the next reader.
+
+use crate::{
+    internal_events::{FifoFramesDropped, FifoReaderConnected},
+    sinks::Healthcheck,
+};
+use bytes::Bytes;
+use futures::{future::BoxFuture, FutureExt};
+use nix::libc;
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::{
+    collections::VecDeque,
+    io,
+    os::unix::{fs::FileTypeExt, fs::OpenOptionsExt, io::AsRawFd},
+    path::{Path, PathBuf},
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tokio::{fs::File, io::AsyncWriteExt, sync::Mutex};
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct FifoSinkConfig {
+    pub path: PathBuf,
+    /// What happens to frames written while no reader has the pipe open.
+    #[serde(default)]
+    pub when_no_reader: NoReaderPolicy,
+    /// With `NoReaderPolicy::Buffer`, the most bytes kept for the next
+    /// reader. The oldest frames are dropped first.
+    #[serde(default = "default_max_buffered_bytes")]
+    pub max_buffered_bytes: usize,
+}
+
+fn default_max_buffered_bytes() -> usize {
+    1024 * 1024
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum NoReaderPolicy {
+    Drop,
+    Buffer,
+}
+
+impl Default for NoReaderPolicy {
+    fn default() -> Self {
+        NoReaderPolicy::Drop
+    }
+}
+
+#[derive(Debug, Snafu)]
+pub enum FifoError {
+    #[snafu(display("Unable to open named pipe: {}", source))]
+    OpenError { source: io::Error },
+    #[snafu(display("{:?} is not a named pipe", path))]
+    NotAFifo { path: PathBuf },
+    #[snafu(display("Write error: {}", source))]
+    WriteError { source: io::Error },
+}
+
+impl FifoSinkConfig {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            when_no_reader: NoReaderPolicy::default(),
+            max_buffered_bytes: default_max_buffered_bytes(),
+        }
+    }
+
+    pub fn build_service(&self) -> crate::Result<(FifoService, Healthcheck)> {
+        let healthcheck = healthcheck(self.path.clone()).boxed();
+        let service = FifoService {
+            config: Arc::new(self.clone()),
+            state: Arc::new(Mutex::new(FifoState::default())),
+        };
+        Ok((service, healthcheck))
+    }
+}
+
+/// Checks that the pipe exists. Having no reader yet isn't an error, as
+/// readers of named pipes come and go.
+async fn healthcheck(path: PathBuf) -> crate::Result<()> {
+    let metadata = tokio::fs::metadata(&path).await.context(OpenError)?;
+    if !metadata.file_type().is_fifo() {
+        return Err(FifoError::NotAFifo { path }.into());
+    }
+    Ok(())
+}
+
+/// Opens the pipe for writing, or returns `None` if no reader has it open.
+fn open(path: &Path) -> io::Result<Option<File>> {
+    // Without a reader, a blocking open would wait for one, while a
+    // non-blocking one fails right away.
+    let file = match std::fs::OpenOptions::new()
+        .write(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path)
+    {
+        Ok(file) => file,
+        Err(error) if error.raw_os_error() == Some(libc::ENXIO) => return Ok(None),
+        Err(error) => return Err(error),
+    };
+    // Writes then block while the pipe is full, on the blocking pool, which
+    // applies backpressure instead of failing.
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_SETFL, 0) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(Some(File::from_std(file)))
+}
+
+#[derive(Default)]
+struct FifoState {
+    /// The pipe, while its reader has it open.
+    pipe: Option<File>,
+    /// Frames not written yet, oldest first.
+    pending: VecDeque<Bytes>,
+    pending_bytes: usize,
+}
+
+impl FifoState {
+    fn push(&mut self, frame: Bytes) {
+        self.pending_bytes += frame.len();
+        self.pending.push_back(frame);
+    }
+
+    fn pop(&mut self) -> Option<Bytes> {
+        let frame = self.pending.pop_front()?;
+        self.pending_bytes -= frame.len();
+        Some(frame)
+    }
+
+    /// Drops the oldest pending frames until at most `max_bytes` remain.
+    fn truncate(&mut self, path: &Path, max_bytes: usize, reason: &'static str) {
+        let (mut count, mut byte_size) = (0, 0);
+        while self.pending_bytes > max_bytes {
+            let frame = self.pop().expect("pending bytes without frames");
+            count += 1;
+            byte_size += frame.len();
+        }
+        if count > 0 {
+            emit!(FifoFramesDropped {
+                path,
+                count,
+                byte_size,
+                reason,
+            });
+        }
+    }
+
+    /// Keeps the pending frames for the next reader, or drops them,
+    /// depending on the policy.
+    fn no_reader(&mut self, config: &FifoSinkConfig) {
+        match config.when_no_reader {
+            NoReaderPolicy::Drop => self.truncate(&config.path, 0, "no_reader"),
+            NoReaderPolicy::Buffer => {
+                self.truncate(&config.path, config.max_buffered_bytes, "buffer_full")
+            }
+        }
+    }
+
+    async fn write(&mut self, config: &FifoSinkConfig, frame: Bytes) -> Result<(), FifoError> {
+        self.push(frame);
+        if self.pipe.is_none() {
+            self.pipe = open(&config.path).context(OpenError)?;
+            match self.pipe {
+                Some(_) => emit!(FifoReaderConnected { path: &config.path }),
+                None => {
+                    self.no_reader(config);
+                    return Ok(());
+                }
+            }
+        }
+
+        while let Some(frame) = self.pending.front().cloned() {
+            let pipe = self.pipe.as_mut().expect("pipe is open");
+            let written = match pipe.write_all(&frame).await {
+                // A write is only done once flushed.
+                Ok(()) => pipe.flush().await,
+                Err(error) => Err(error),
+            };
+            match written {
+                Ok(()) => {
+                    self.pop();
+                }
+                Err(error) if error.kind() == io::ErrorKind::BrokenPipe => {
+                    // The reader went away; the frame is handled like the
+                    // ones written without a reader.
+                    self.pipe = None;
+                    self.no_reader(config);
+                    return Ok(());
+                }
+                Err(error) => {
+                    self.pipe = None;
+                    self.pop();
+                    return Err(FifoError::WriteError { source: error });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct FifoService {
+    config: Arc<FifoSinkConfig>,
+    state: Arc<Mutex<FifoState>>,
+}
+
+impl tower::Service<Bytes> for FifoService {
+    type Response = ();
+    type Error = FifoError;
+    type Future = BoxFuture<'static, Result<(), Self::Error>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, msg: Bytes) -> Self::Future {
+        let config = Arc::clone(&self.config);
+        let state = Arc::clone(&self.state);
+        async move { state.lock().await.write(&config, msg).await }.boxed()
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use nix::{sys::stat, unistd};
+    use std::{io::Read, time::Duration};
+    use tower::Service;
+
+    /// Creates a named pipe in a new temporary directory.
+    pub fn make_fifo(name: &str) -> PathBuf {
+        let path = tempfile::tempdir().unwrap().into_path().join(name);
+        unistd::mkfifo(path.as_path(), stat::Mode::S_IRWXU).unwrap();
+        path
+    }
+
+    /// Opens the read end of the pipe at `path` without waiting for a
+    /// writer.
+    pub fn open_reader(path: &Path) -> std::fs::File {
+        std::fs::OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(path)
+            .unwrap()
+    }
+
+    /// Reads `len` bytes from `reader`, waiting up to a second for them.
+    pub async fn read(reader: &mut std::fs::File, len: usize) -> Vec<u8> {
+        let mut received = Vec::new();
+        let mut buf = [0; 1024];
+        for _ in 0..100 {
+            match reader.read(&mut buf) {
+                // The writer closed the pipe.
+                Ok(0) => break,
+                Ok(read) => received.extend_from_slice(&buf[..read]),
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => (),
+                Err(error) => panic!("Unable to read named pipe: {}", error),
+            }
+            if received.len() >= len {
+                break;
+            }
+            tokio::time::delay_for(Duration::from_millis(10)).await;
+        }
+        received
+    }
+
+    #[tokio::test]
+    async fn writes_to_reader() {
+        let path = make_fifo("writes");
+        let mut reader = open_reader(&path);
+        let (mut service, healthcheck) = FifoSinkConfig::new(path).build_service().unwrap();
+        healthcheck.await.unwrap();
+
+        service.call(Bytes::from("foo:1|c\n")).await.unwrap();
+        service.call(Bytes::from("bar:2|g\n")).await.unwrap();
+        assert_eq!(read(&mut reader, 16).await, b"foo:1|c\nbar:2|g\n".to_vec());
+    }
+
+    async fn written_after_no_reader(policy: NoReaderPolicy, max_buffered_bytes: usize) -> Vec<u8> {
+        let path = make_fifo("no_reader");
+        let mut config = FifoSinkConfig::new(path.clone());
+        config.when_no_reader = policy;
+        config.max_buffered_bytes = max_buffered_bytes;
+        let (mut service, _) = config.build_service().unwrap();
+
+        service.call(Bytes::from("foo:1|c\n")).await.unwrap();
+        service.call(Bytes::from("bar:2|g\n")).await.unwrap();
+        let mut reader = open_reader(&path);
+        service.call(Bytes::from("baz:3|c\n")).await.unwrap();
+        drop(service);
+        read(&mut reader, 24).await
+    }
+
+    #[tokio::test]
+    async fn drops_frames_without_reader() {
+        assert_eq!(
+            written_after_no_reader(NoReaderPolicy::Drop, 1024).await,
+            b"baz:3|c\n".to_vec()
+        );
+    }
+
+    #[tokio::test]
+    async fn buffers_frames_without_reader() {
+        assert_eq!(
+            written_after_no_reader(NoReaderPolicy::Buffer, 1024).await,
+            b"foo:1|c\nbar:2|g\nbaz:3|c\n".to_vec()
+        );
+        assert_eq!(
+            written_after_no_reader(NoReaderPolicy::Buffer, 8).await,
+            b"bar:2|g\nbaz:3|c\n".to_vec()
+        );
+    }
+
+    #[tokio::test]
+    async fn healthcheck_requires_fifo() {
+        let path = make_fifo("healthcheck");
+        assert!(healthcheck(path.clone()).await.is_ok());
+
+        let file = path.with_file_name("regular");
+        std::fs::write(&file, b"").unwrap();
+        assert!(healthcheck(file).await.is_err());
+        assert!(healthcheck(path.with_file_name("missing")).await.is_err());
+    }
+}
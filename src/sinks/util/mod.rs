@@ -2,12 +2,16 @@ pub mod auto_concurrency;
 pub mod batch;
 pub mod buffer;
 pub mod encoding;
+#[cfg(all(feature = "sinks-statsd", unix))]
+pub mod fifo;
 pub mod http;
 pub mod retries;
 #[cfg(feature = "rusoto_core")]
 pub mod rusoto;
 pub mod service;
 pub mod sink;
+pub mod socks5;
+pub mod srv;
 pub mod statistic;
 pub mod tcp;
 #[cfg(test)]
@@ -22,7 +26,7 @@ use bytes::Bytes;
 use encoding::{EncodingConfig, EncodingConfiguration};
 use serde::{Deserialize, Serialize};
 use snafu::Snafu;
-use std::borrow::Cow;
+use std::{borrow::Cow, sync::Arc};
 
 pub use batch::{Batch, BatchConfig, BatchSettings, BatchSize, PushResult};
 pub use buffer::json::{BoxedRawValue, JsonArrayBuffer};
@@ -34,7 +38,7 @@ pub use service::{
     InFlightLimit, ServiceBuilderExt, TowerBatchedSink, TowerRequestConfig, TowerRequestLayer,
     TowerRequestSettings,
 };
-pub use sink::{BatchSink, PartitionBatchSink, StreamSink, StreamSinkOld};
+pub use sink::{BatchSink, Dropped, OverflowPolicy, PartitionBatchSink, StreamSink, StreamSinkOld};
 pub use uri::UriSerde;
 
 #[derive(Debug, Snafu)]
@@ -45,6 +49,40 @@ enum SinkBuildError {
     MissingPort,
 }
 
+/// Lifecycle of a connection held by one of the batched stream services.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ConnectionEvent {
+    Opened,
+    Closed,
+}
+
+/// Called by the batched stream services whenever they open or close a
+/// connection, so the sink using them can report connection churn.
+pub type ConnectionHook = Arc<dyn Fn(ConnectionEvent) + Send + Sync>;
+
+/// Reports `Opened` when created and `Closed` when dropped, so it can be
+/// held alongside a connection to cover every way it can be closed.
+struct ConnectionGuard {
+    hook: Option<ConnectionHook>,
+}
+
+impl ConnectionGuard {
+    fn opened(hook: &Option<ConnectionHook>) -> Self {
+        if let Some(hook) = hook {
+            hook(ConnectionEvent::Opened);
+        }
+        Self { hook: hook.clone() }
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        if let Some(hook) = &self.hook {
+            hook(ConnectionEvent::Closed);
+        }
+    }
+}
+
 /**
  * Enum representing different ways to encode events as they are sent into a Sink.
  */
@@ -86,7 +124,7 @@ pub fn encode_event(mut event: Event, encoding: &EncodingConfig<Encoding>) -> Op
 /// Joins namespace with name via delimiter if namespace is present and not empty.
 pub fn encode_namespace<'a>(
     namespace: Option<&str>,
-    delimiter: char,
+    delimiter: impl std::fmt::Display,
     name: impl Into<Cow<'a, str>>,
 ) -> String {
     let name = name.into();
@@ -36,7 +36,7 @@ use super::{
     batch::{Batch, PushResult, StatefulBatch},
     buffer::partition::Partition,
 };
-use crate::{buffers::Acker, Event};
+use crate::{buffers::Acker, internal_events::BatchEventsDropped, Event};
 use async_trait::async_trait;
 use futures::{
     compat::{Compat, Future01CompatExt},
@@ -47,14 +47,17 @@ use futures01::{
     future::Either, stream::FuturesUnordered, sync::oneshot, try_ready, Async, AsyncSink, Future,
     Poll, Sink, StartSend, Stream,
 };
+use serde::{Deserialize, Serialize};
 use std::{
     collections::{HashMap, VecDeque},
     convert::Infallible,
     fmt,
     hash::Hash,
     marker::PhantomData,
+    mem,
+    time::{SystemTime, UNIX_EPOCH},
 };
-use tokio::time::{delay_for, Duration};
+use tokio::time::{delay_for, Duration, Instant};
 use tower::Service;
 use tracing_futures::Instrument;
 
@@ -163,9 +166,52 @@ pub struct BatchSink<S, B, Request> {
     linger: Option<SafeLinger>,
     closing: bool,
     service_was_not_ready: bool,
+    overflow_policy: OverflowPolicy,
+    /// Whether batches are flushed on wall-clock multiples of `timeout`
+    /// instead of `timeout` after their first event.
+    align_timeout: bool,
+    /// Whether the linger timeout shrinks as the batch fills up.
+    adaptive_timeout: bool,
+    /// When set, a batch is also flushed once this long has passed without
+    /// a new event.
+    idle_timeout: Option<Duration>,
+    linger_started: Instant,
+    linger_deadline: Instant,
+    /// Events dropped since the last request, which are acked along with
+    /// the next request so acks stay in order.
+    dropped: usize,
+    /// When set, called with whatever the overflow policy drops.
+    drop_hook: Option<Box<dyn FnMut(Dropped<B>) + Send>>,
     _pd: PhantomData<Request>,
 }
 
+/// What a `BatchSink` does with a new event while its batch is full and
+/// the service isn't ready to take it.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    /// Applies back pressure until the batch can be sent.
+    Block,
+    /// Drops the new event.
+    DropNewest,
+    /// Drops the full batch and starts a new one with the new event.
+    DropOldest,
+}
+
+impl Default for OverflowPolicy {
+    fn default() -> Self {
+        OverflowPolicy::Block
+    }
+}
+
+/// What an `OverflowPolicy` dropped.
+pub enum Dropped<B: Batch> {
+    /// The new event, with `DropNewest`.
+    Event(B::Input),
+    /// The full batch it replaced, with `DropOldest`.
+    Batch(B::Output),
+}
+
 impl<S, B, Request> BatchSink<S, B, Request>
 where
     S: Service<Request>,
@@ -184,14 +230,76 @@ where
             linger: None,
             closing: false,
             service_was_not_ready: false,
+            overflow_policy: OverflowPolicy::default(),
+            align_timeout: false,
+            adaptive_timeout: false,
+            idle_timeout: None,
+            linger_started: Instant::now(),
+            linger_deadline: Instant::now(),
+            dropped: 0,
+            drop_hook: None,
             _pd: PhantomData,
         }
     }
 
+    pub fn with_overflow_policy(mut self, overflow_policy: OverflowPolicy) -> Self {
+        self.overflow_policy = overflow_policy;
+        self
+    }
+
+    /// Sets a hook called with the events the overflow policy drops, so
+    /// anything accounted for them when they were sent can be released.
+    pub fn with_drop_hook(mut self, hook: impl FnMut(Dropped<B>) + Send + 'static) -> Self {
+        self.drop_hook = Some(Box::new(hook));
+        self
+    }
+
+    pub fn with_aligned_timeout(mut self, align_timeout: bool) -> Self {
+        self.align_timeout = align_timeout;
+        self
+    }
+
+    pub fn with_adaptive_timeout(mut self, adaptive_timeout: bool) -> Self {
+        self.adaptive_timeout = adaptive_timeout;
+        self
+    }
+
+    pub fn with_idle_timeout(mut self, idle_timeout: Option<Duration>) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    /// The number of events waiting in the current batch.
+    pub fn pending_events(&self) -> usize {
+        self.batch.num_items()
+    }
+
     fn should_send(&mut self) -> bool {
         self.closing || self.batch.was_full() || self.linger_elapsed()
     }
 
+    /// Brings the linger deadline forward in proportion to how full the
+    /// batch is. The deadline is never pushed back.
+    fn adapt_linger(&mut self) {
+        let deadline = self.linger_started + adaptive_linger(self.timeout, self.batch.fill_ratio());
+        if deadline < self.linger_deadline {
+            self.linger_deadline = deadline;
+            self.reset_linger();
+        }
+    }
+
+    /// Rearms the linger timeout for the batch's deadline or, with an idle
+    /// timeout, for `idle_timeout` from now if that's sooner.
+    fn reset_linger(&mut self) {
+        let now = Instant::now();
+        let deadline = match self.idle_timeout {
+            Some(idle_timeout) => self.linger_deadline.min(now + idle_timeout),
+            None => self.linger_deadline,
+        };
+        let timeout = deadline.saturating_duration_since(now);
+        self.linger = Some(SafeLinger::new(timeout));
+    }
+
     fn linger_elapsed(&mut self) -> bool {
         match &mut self.linger {
             Some(linger) => linger.poll().expect("timer error").is_ready(),
@@ -227,11 +335,39 @@ where
             self.poll_complete()?;
 
             if !self.batch.is_empty() {
-                debug!(
-                    message = "Batch buffer full; applying back pressure.",
-                    rate_limit_secs = 10
-                );
-                return Ok(AsyncSink::NotReady(item));
+                match self.overflow_policy {
+                    OverflowPolicy::Block => {
+                        debug!(
+                            message = "Batch buffer full; applying back pressure.",
+                            rate_limit_secs = 10
+                        );
+                        return Ok(AsyncSink::NotReady(item));
+                    }
+                    OverflowPolicy::DropNewest => {
+                        self.dropped += 1;
+                        emit!(BatchEventsDropped {
+                            count: 1,
+                            policy: "drop_newest",
+                        });
+                        if let Some(hook) = &mut self.drop_hook {
+                            hook(Dropped::Event(item));
+                        }
+                        return Ok(AsyncSink::Ready);
+                    }
+                    OverflowPolicy::DropOldest => {
+                        let batch = self.batch.fresh_replace();
+                        let count = batch.num_items();
+                        self.dropped += count;
+                        self.linger = None;
+                        emit!(BatchEventsDropped {
+                            count,
+                            policy: "drop_oldest",
+                        });
+                        if let Some(hook) = &mut self.drop_hook {
+                            hook(Dropped::Batch(batch.finish()));
+                        }
+                    }
+                }
             }
         }
 
@@ -239,12 +375,27 @@ where
             trace!("Starting new batch timer.");
             // We just inserted the first item of a new batch, so set our delay to the longest time
             // we want to allow that item to linger in the batch before being flushed.
-            let delay = SafeLinger::new(self.timeout);
+            let timeout = if self.align_timeout {
+                until_boundary(SystemTime::now(), self.timeout)
+            } else {
+                self.timeout
+            };
+            self.linger_started = Instant::now();
+            self.linger_deadline = self.linger_started + timeout;
+            let delay = SafeLinger::new(timeout);
             self.linger = Some(delay);
         }
 
         match self.batch.push(item) {
-            PushResult::Ok(false) => Ok(AsyncSink::Ready),
+            PushResult::Ok(false) => {
+                if self.adaptive_timeout {
+                    self.adapt_linger();
+                }
+                if self.idle_timeout.is_some() {
+                    self.reset_linger();
+                }
+                Ok(AsyncSink::Ready)
+            }
             PushResult::Ok(true) => {
                 self.poll_complete()?;
                 Ok(AsyncSink::Ready)
@@ -274,7 +425,7 @@ where
                     trace!("Service ready; Sending batch.");
                     let batch = self.batch.fresh_replace();
 
-                    let batch_size = batch.num_items();
+                    let batch_size = batch.num_items() + mem::take(&mut self.dropped);
                     let request = batch.finish();
 
                     let fut = self.service.call(request, batch_size).compat();
@@ -325,6 +476,22 @@ where
     }
 }
 
+/// The time from `now` until the next wall-clock multiple of `period`.
+fn until_boundary(now: SystemTime, period: Duration) -> Duration {
+    let period = period.as_nanos().max(1);
+    let elapsed = now
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    Duration::from_nanos((period - elapsed % period) as u64)
+}
+
+/// The linger timeout for a batch that is `fill_ratio` full, which shrinks
+/// linearly from `timeout` when empty to nothing when full.
+fn adaptive_linger(timeout: Duration, fill_ratio: f64) -> Duration {
+    timeout.mul_f64(1.0 - fill_ratio.max(0.0).min(1.0))
+}
+
 struct SafeLinger {
     inner: Box<dyn Future<Item = (), Error = Infallible> + Send>,
     finished: bool,
@@ -806,7 +973,13 @@ mod tests {
     use bytes::Bytes;
     use futures::{compat::Future01CompatExt, future};
     use futures01::{future as future01, Sink};
-    use std::sync::{atomic::Ordering::Relaxed, Arc, Mutex};
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering::Relaxed},
+            Arc, Mutex,
+        },
+        task::{self, Context},
+    };
     use tokio::task::yield_now;
 
     const TIMEOUT: Duration = Duration::from_secs(10);
@@ -1015,6 +1188,184 @@ mod tests {
         .await;
     }
 
+    #[tokio::test]
+    async fn batch_sink_adaptive_linger_flushes_fuller_batches_sooner() {
+        run_as_future01(async {
+            let batch = BatchSettings::default().bytes(9999).events(10);
+            let sink = |svc: GatedService| {
+                svc.open.store(true, Relaxed);
+                let (acker, _) = Acker::new_for_testing();
+                BatchSink::new(svc, VecBuffer::new(batch.size), TIMEOUT, acker)
+                    .with_adaptive_timeout(true)
+            };
+
+            let nearly_empty = GatedService::default();
+            let mut nearly_empty_sink = sink(nearly_empty.clone());
+            assert!(nearly_empty_sink.start_send(0).unwrap().is_ready());
+
+            let nearly_full = GatedService::default();
+            let mut nearly_full_sink = sink(nearly_full.clone());
+            for i in 0..9 {
+                assert!(nearly_full_sink.start_send(i).unwrap().is_ready());
+            }
+
+            // A 90% full batch lingers for a tenth of the timeout, while a
+            // 10% full one still has most of it left.
+            advance_time(Duration::from_secs(2)).await;
+
+            while nearly_full_sink.poll_complete().unwrap() == Async::NotReady {
+                yield_now().await;
+            }
+            assert_eq!(nearly_empty_sink.poll_complete().unwrap(), Async::NotReady);
+
+            assert_eq!(
+                *nearly_full.sent_requests.lock().unwrap(),
+                vec![(0..9).collect::<Vec<_>>()]
+            );
+            assert!(nearly_empty.sent_requests.lock().unwrap().is_empty());
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn batch_sink_idle_timeout_flushes_after_burst() {
+        run_as_future01(async {
+            let svc = GatedService::default();
+            svc.open.store(true, Relaxed);
+            let batch = BatchSettings::default().bytes(9999).events(10);
+            let (acker, _) = Acker::new_for_testing();
+            let mut sink = BatchSink::new(svc.clone(), VecBuffer::new(batch.size), TIMEOUT, acker)
+                .with_idle_timeout(Some(Duration::from_secs(1)));
+
+            // Each event of the burst restarts the idle timeout.
+            for i in 0..3 {
+                assert!(sink.start_send(i).unwrap().is_ready());
+                advance_time(Duration::from_millis(800)).await;
+                assert_eq!(sink.poll_complete().unwrap(), Async::NotReady);
+            }
+            assert!(svc.sent_requests.lock().unwrap().is_empty());
+
+            // Once it's over, the partial batch goes out well before `TIMEOUT`.
+            advance_time(Duration::from_millis(200)).await;
+            while sink.poll_complete().unwrap() == Async::NotReady {
+                yield_now().await;
+            }
+            assert_eq!(*svc.sent_requests.lock().unwrap(), vec![vec![0, 1, 2]]);
+        })
+        .await;
+    }
+
+    #[test]
+    fn adaptive_linger_shrinks_with_fill_ratio() {
+        assert_eq!(adaptive_linger(TIMEOUT, 0.0), TIMEOUT);
+        assert_eq!(adaptive_linger(TIMEOUT, 0.75), Duration::from_millis(2_500));
+        assert_eq!(adaptive_linger(TIMEOUT, 1.5), Duration::from_secs(0));
+    }
+
+    /// A service that isn't ready until `open` is set.
+    #[derive(Clone, Default)]
+    struct GatedService {
+        open: Arc<AtomicBool>,
+        sent_requests: Arc<Mutex<Vec<Vec<usize>>>>,
+    }
+
+    impl Service<Vec<usize>> for GatedService {
+        type Response = ();
+        type Error = std::io::Error;
+        type Future = future::Ready<Result<(), Self::Error>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> task::Poll<Result<(), Self::Error>> {
+            if self.open.load(Relaxed) {
+                task::Poll::Ready(Ok(()))
+            } else {
+                task::Poll::Pending
+            }
+        }
+
+        fn call(&mut self, req: Vec<usize>) -> Self::Future {
+            self.sent_requests.lock().unwrap().push(req);
+            future::ok(())
+        }
+    }
+
+    /// Sends three events through a sink holding two events per batch while
+    /// the service isn't ready, then opens the service and closes the sink.
+    /// Returns whether the third event was accepted, the sent requests, the
+    /// number of acked events and the events given to the drop hook.
+    async fn saturate(policy: OverflowPolicy) -> (bool, Vec<Vec<usize>>, usize, Vec<usize>) {
+        run_as_future01(async move {
+            let (acker, ack_counter) = Acker::new_for_testing();
+            let svc = GatedService::default();
+            let dropped = Arc::new(Mutex::new(Vec::new()));
+
+            let batch = BatchSettings::default().bytes(9999).events(2);
+            let mut buffered =
+                BatchSink::new(svc.clone(), VecBuffer::new(batch.size), TIMEOUT, acker)
+                    .with_overflow_policy(policy)
+                    .with_drop_hook({
+                        let dropped = Arc::clone(&dropped);
+                        move |events| match events {
+                            Dropped::Event(event) => dropped.lock().unwrap().push(event),
+                            Dropped::Batch(batch) => dropped.lock().unwrap().extend(batch),
+                        }
+                    });
+
+            assert!(buffered.start_send(0).unwrap().is_ready());
+            assert!(buffered.start_send(1).unwrap().is_ready());
+            let accepted = buffered.start_send(2).unwrap().is_ready();
+
+            svc.open.store(true, Relaxed);
+            futures01::future::poll_fn(|| buffered.close())
+                .compat()
+                .await
+                .unwrap();
+
+            let sent_requests = svc.sent_requests.lock().unwrap().clone();
+            let dropped = dropped.lock().unwrap().clone();
+            (accepted, sent_requests, ack_counter.load(Relaxed), dropped)
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn batch_sink_overflow_blocks() {
+        assert_eq!(
+            saturate(OverflowPolicy::Block).await,
+            (false, vec![vec![0, 1]], 2, vec![])
+        );
+    }
+
+    #[tokio::test]
+    async fn batch_sink_overflow_drops_newest() {
+        assert_eq!(
+            saturate(OverflowPolicy::DropNewest).await,
+            (true, vec![vec![0, 1]], 3, vec![2])
+        );
+    }
+
+    #[tokio::test]
+    async fn batch_sink_overflow_drops_oldest() {
+        assert_eq!(
+            saturate(OverflowPolicy::DropOldest).await,
+            (true, vec![vec![2]], 3, vec![0, 1])
+        );
+    }
+
+    #[test]
+    fn aligned_timeout_ends_on_boundary() {
+        let period = Duration::from_secs(10);
+        let at = |millis| UNIX_EPOCH + Duration::from_millis(millis);
+
+        // Events enqueued at different times within a window are flushed
+        // together at its end.
+        assert_eq!(until_boundary(at(21_000), period), Duration::from_secs(9));
+        assert_eq!(
+            until_boundary(at(27_500), period),
+            Duration::from_millis(2_500)
+        );
+        assert_eq!(until_boundary(at(30_000), period), period);
+    }
+
     #[tokio::test]
     async fn partition_batch_sink_buffers_messages_until_limit() {
         let (acker, _) = Acker::new_for_testing();
@@ -0,0 +1,171 @@
+//! A minimal SOCKS5 client (RFC 1928) for relaying UDP datagrams through
+//! a proxy with the `UDP ASSOCIATE` command. Only proxies that don't
+//! require authentication are supported.
+
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use std::{
+    convert::TryFrom,
+    io,
+    net::{IpAddr, SocketAddr},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+const VERSION: u8 = 5;
+const NO_AUTHENTICATION: u8 = 0;
+const UDP_ASSOCIATE: u8 = 3;
+const SUCCEEDED: u8 = 0;
+const ADDRESS_IPV4: u8 = 1;
+const ADDRESS_DOMAIN: u8 = 3;
+const ADDRESS_IPV6: u8 = 4;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProxyConfig {
+    /// The `host:port` of the SOCKS5 proxy.
+    pub address: String,
+}
+
+/// A UDP association with a proxy, which relays datagrams sent to its
+/// relay address to a single target. The association lasts as long as
+/// its control connection, so it must be kept around while sending.
+#[derive(Debug)]
+pub struct Relay {
+    address: SocketAddr,
+    header: Bytes,
+    _control: TcpStream,
+}
+
+impl Relay {
+    /// Asks the proxy at `proxy` to relay datagrams to `host:port`. The
+    /// host is passed on to the proxy as is, so names are resolved by the
+    /// proxy rather than locally.
+    pub async fn associate(proxy: &str, host: &str, port: u16) -> io::Result<Self> {
+        let header = header(host, port)?;
+        let mut control = TcpStream::connect(proxy).await?;
+
+        control.write_all(&[VERSION, 1, NO_AUTHENTICATION]).await?;
+        let mut reply = [0; 2];
+        control.read_exact(&mut reply).await?;
+        if reply != [VERSION, NO_AUTHENTICATION] {
+            return Err(invalid("proxy requires authentication"));
+        }
+
+        // The datagrams' source isn't known before sending, which an
+        // unspecified address and port signal.
+        control
+            .write_all(&[VERSION, UDP_ASSOCIATE, 0, ADDRESS_IPV4, 0, 0, 0, 0, 0, 0])
+            .await?;
+        let mut reply = [0; 4];
+        control.read_exact(&mut reply).await?;
+        if reply[0] != VERSION {
+            return Err(invalid("proxy doesn't speak SOCKS5"));
+        }
+        if reply[1] != SUCCEEDED {
+            return Err(invalid(&format!(
+                "proxy rejected the UDP association with code {}",
+                reply[1]
+            )));
+        }
+        let ip = match reply[3] {
+            ADDRESS_IPV4 => {
+                let mut ip = [0; 4];
+                control.read_exact(&mut ip).await?;
+                IpAddr::from(ip)
+            }
+            ADDRESS_IPV6 => {
+                let mut ip = [0; 16];
+                control.read_exact(&mut ip).await?;
+                IpAddr::from(ip)
+            }
+            _ => return Err(invalid("unsupported relay address type")),
+        };
+        let mut port = [0; 2];
+        control.read_exact(&mut port).await?;
+
+        // An unspecified relay address stands for the proxy's own.
+        let ip = if ip.is_unspecified() {
+            control.peer_addr()?.ip()
+        } else {
+            ip
+        };
+
+        Ok(Self {
+            address: SocketAddr::new(ip, u16::from_be_bytes(port)),
+            header,
+            _control: control,
+        })
+    }
+
+    /// Where datagrams to relay are sent.
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Wraps `datagram` in the header telling the proxy where to relay it.
+    pub fn frame(&self, datagram: &[u8]) -> Bytes {
+        let mut framed = Vec::with_capacity(self.header.len() + datagram.len());
+        framed.extend_from_slice(&self.header);
+        framed.extend_from_slice(datagram);
+        framed.into()
+    }
+
+    /// The number of bytes `frame` adds to each datagram.
+    pub fn overhead(&self) -> usize {
+        self.header.len()
+    }
+}
+
+/// The header of a datagram relayed to `host:port`: two reserved bytes,
+/// the fragment number and the target address.
+fn header(host: &str, port: u16) -> io::Result<Bytes> {
+    let mut header = vec![0, 0, 0];
+    // IPv6 literals come bracketed from URIs.
+    let unbracketed = host.trim_start_matches('[').trim_end_matches(']');
+    match unbracketed.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            header.push(ADDRESS_IPV4);
+            header.extend_from_slice(&ip.octets());
+        }
+        Ok(IpAddr::V6(ip)) => {
+            header.push(ADDRESS_IPV6);
+            header.extend_from_slice(&ip.octets());
+        }
+        Err(_) => {
+            let len = u8::try_from(host.len()).map_err(|_| invalid("host name is too long"))?;
+            header.push(ADDRESS_DOMAIN);
+            header.push(len);
+            header.extend_from_slice(host.as_bytes());
+        }
+    }
+    header.extend_from_slice(&port.to_be_bytes());
+    Ok(header.into())
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn builds_headers() {
+        assert_eq!(
+            &header("10.0.0.1", 8125).unwrap()[..],
+            &[0, 0, 0, ADDRESS_IPV4, 10, 0, 0, 1, 0x1f, 0xbd]
+        );
+        let mut ipv6 = vec![0, 0, 0, ADDRESS_IPV6];
+        ipv6.extend_from_slice(&[0; 15]);
+        ipv6.extend_from_slice(&[1, 0x1f, 0xbd]);
+        assert_eq!(&header("[::1]", 8125).unwrap()[..], &ipv6[..]);
+        assert_eq!(
+            &header("statsd", 8125).unwrap()[..],
+            &b"\x00\x00\x00\x03\x06statsd\x1f\xbd"[..]
+        );
+    }
+}
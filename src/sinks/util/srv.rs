@@ -0,0 +1,206 @@
+//! Looks up SRV records (RFC 2782), which the system resolver can't do,
+//! and picks the target to connect to among them.
+
+use rand::random;
+use std::net::SocketAddr;
+use trust_dns_resolver::{
+    config::{NameServerConfigGroup, ResolverConfig, ResolverOpts},
+    error::{ResolveError, ResolveErrorKind},
+    TokioAsyncResolver,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SrvRecord {
+    pub priority: u16,
+    pub weight: u16,
+    pub port: u16,
+    pub target: String,
+}
+
+/// Looks up SRV records with the name server at `nameserver`, or the ones
+/// in the system configuration when not set.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SrvResolver {
+    nameserver: Option<SocketAddr>,
+}
+
+impl SrvResolver {
+    pub fn with_nameserver(nameserver: SocketAddr) -> Self {
+        Self {
+            nameserver: Some(nameserver),
+        }
+    }
+
+    /// Looks up the SRV records of `name`. A name that doesn't exist has
+    /// no records, and neither does one whose only target is `.`, meaning
+    /// the service isn't available.
+    pub async fn lookup(self, name: &str) -> Result<Vec<SrvRecord>, ResolveError> {
+        let resolver = match self.nameserver {
+            Some(nameserver) => {
+                let nameservers =
+                    NameServerConfigGroup::from_ips_clear(&[nameserver.ip()], nameserver.port());
+                let config = ResolverConfig::from_parts(None, Vec::new(), nameservers);
+                TokioAsyncResolver::tokio(config, ResolverOpts::default()).await?
+            }
+            None => TokioAsyncResolver::tokio_from_system_conf().await?,
+        };
+
+        let lookup = match resolver.srv_lookup(name).await {
+            Ok(lookup) => lookup,
+            Err(error) => match error.kind() {
+                ResolveErrorKind::NoRecordsFound { .. } => return Ok(Vec::new()),
+                _ => return Err(error),
+            },
+        };
+        Ok(lookup
+            .iter()
+            .filter(|srv| !srv.target().is_root())
+            .map(|srv| SrvRecord {
+                priority: srv.priority(),
+                weight: srv.weight(),
+                port: srv.port(),
+                target: srv.target().to_utf8().trim_end_matches('.').to_owned(),
+            })
+            .collect())
+    }
+}
+
+/// Picks the record to connect to: one of those with the lowest priority,
+/// chosen at random in proportion to their weights.
+pub fn select(records: &[SrvRecord]) -> Option<&SrvRecord> {
+    select_with(records, random())
+}
+
+/// `select`, with `pick` standing in for the random number.
+fn select_with(records: &[SrvRecord], pick: u32) -> Option<&SrvRecord> {
+    let priority = records.iter().map(|record| record.priority).min()?;
+    let candidates = records
+        .iter()
+        .filter(|record| record.priority == priority)
+        .collect::<Vec<_>>();
+
+    let total = candidates
+        .iter()
+        .map(|record| u32::from(record.weight))
+        .sum::<u32>();
+    if total == 0 {
+        return candidates.first().copied();
+    }
+    let mut pick = pick % total;
+    for record in &candidates {
+        let weight = u32::from(record.weight);
+        if pick < weight {
+            return Some(record);
+        }
+        pick -= weight;
+    }
+    candidates.last().copied()
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use tokio::net::UdpSocket;
+
+    const TYPE_SRV: u16 = 33;
+    const CLASS_IN: u16 = 1;
+
+    /// Answers one SRV query on `socket` with `records`.
+    pub async fn mock_nameserver(socket: UdpSocket, records: Vec<SrvRecord>) {
+        mock_nameserver_sequence(socket, vec![records]).await
+    }
+
+    /// Answers successive SRV queries on `socket` with each of `answers` in
+    /// turn.
+    pub async fn mock_nameserver_sequence(mut socket: UdpSocket, answers: Vec<Vec<SrvRecord>>) {
+        for records in answers {
+            answer(&mut socket, records).await;
+        }
+    }
+
+    async fn answer(socket: &mut UdpSocket, records: Vec<SrvRecord>) {
+        let mut buf = [0; 512];
+        let (len, peer) = socket.recv_from(&mut buf).await.unwrap();
+        let query = &buf[..len];
+
+        let mut response = query[..2].to_vec();
+        response.extend_from_slice(&[0x81, 0x80, 0, 1]);
+        response.extend_from_slice(&(records.len() as u16).to_be_bytes());
+        response.extend_from_slice(&[0, 0, 0, 0]);
+        // The question, whose name the answers point back to.
+        response.extend_from_slice(&query[12..]);
+        for record in records {
+            let mut target = Vec::new();
+            for label in record.target.split('.').filter(|label| !label.is_empty()) {
+                target.push(label.len() as u8);
+                target.extend_from_slice(label.as_bytes());
+            }
+            target.push(0);
+
+            response.extend_from_slice(&[0xc0, 12]);
+            response.extend_from_slice(&TYPE_SRV.to_be_bytes());
+            response.extend_from_slice(&CLASS_IN.to_be_bytes());
+            // No TTL, so every lookup reaches the name server.
+            response.extend_from_slice(&0u32.to_be_bytes());
+            response.extend_from_slice(&(6 + target.len() as u16).to_be_bytes());
+            response.extend_from_slice(&record.priority.to_be_bytes());
+            response.extend_from_slice(&record.weight.to_be_bytes());
+            response.extend_from_slice(&record.port.to_be_bytes());
+            response.extend_from_slice(&target);
+        }
+        socket.send_to(&response, peer).await.unwrap();
+    }
+
+    fn record(priority: u16, weight: u16, target: &str) -> SrvRecord {
+        SrvRecord {
+            priority,
+            weight,
+            port: 8125,
+            target: target.to_owned(),
+        }
+    }
+
+    #[tokio::test]
+    async fn looks_up_records() {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let nameserver = socket.local_addr().unwrap();
+        let records = vec![
+            record(10, 5, "a.example.com"),
+            record(20, 0, "b.example.com"),
+        ];
+        tokio::spawn(mock_nameserver(socket, records.clone()));
+
+        let resolver = SrvResolver::with_nameserver(nameserver);
+        assert_eq!(
+            resolver.lookup("_statsd._udp.example.com").await.unwrap(),
+            records
+        );
+    }
+
+    #[tokio::test]
+    async fn skips_unavailable_service() {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let nameserver = socket.local_addr().unwrap();
+        tokio::spawn(mock_nameserver(socket, vec![record(0, 0, ".")]));
+
+        let resolver = SrvResolver::with_nameserver(nameserver);
+        assert_eq!(
+            resolver.lookup("_statsd._udp.example.com").await.unwrap(),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn selects_by_priority_then_weight() {
+        let records = vec![
+            record(20, 100, "backup"),
+            record(10, 1, "light"),
+            record(10, 3, "heavy"),
+        ];
+        assert_eq!(select_with(&records, 0).unwrap().target, "light");
+        assert_eq!(select_with(&records, 1).unwrap().target, "heavy");
+        assert_eq!(select_with(&records, 7).unwrap().target, "heavy");
+        assert_eq!(select_with(&records, 8).unwrap().target, "light");
+        assert_eq!(select_with(&[], 0), None);
+    }
+}
@@ -7,7 +7,8 @@ use crate::{
         TcpConnectionShutdown, TcpEventSent, TcpFlushError,
     },
     sinks::util::{
-        encode_event, encoding::EncodingConfig, Encoding, SinkBuildError, StreamSinkOld,
+        encode_event, encoding::EncodingConfig, ConnectionGuard, ConnectionHook, Encoding,
+        SinkBuildError, StreamSinkOld,
     },
     sinks::{Healthcheck, VectorSink},
     tls::{MaybeTlsSettings, MaybeTlsStream, TlsConfig, TlsError},
@@ -19,17 +20,23 @@ use futures::{
 use futures01::{
     stream::iter_ok, try_ready, Async, AsyncSink, Future, Poll as Poll01, Sink, StartSend,
 };
+#[cfg(unix)]
+use nix::libc;
 use serde::{Deserialize, Serialize};
 use snafu::{ResultExt, Snafu};
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
 use std::{
     net::SocketAddr,
     pin::Pin,
+    sync::Arc,
     task::{Context, Poll},
-    time::Duration,
+    time::{Duration, Instant},
 };
 use tokio::{
     io::AsyncRead,
     net::TcpStream,
+    sync::Mutex,
     time::{delay_for, Delay},
 };
 use tokio_retry::strategy::ExponentialBackoff;
@@ -40,20 +47,52 @@ use tokio_util::codec::{BytesCodec, FramedWrite};
 pub struct TcpSinkConfig {
     pub address: String,
     pub tls: Option<TlsConfig>,
+    /// The type of service (TOS) byte, or IPv6 traffic class, marking the
+    /// connection's packets.
+    pub tos: Option<u8>,
+    /// The Linux `SO_PRIORITY` of the connection, which picks its queue on
+    /// the outgoing interface.
+    pub priority: Option<u32>,
 }
 
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionMode {
+    /// One connection is kept open, with TCP keepalive, and only replaced
+    /// after it fails.
+    Persistent,
+    /// Each batch is sent on a new connection, unless `idle_timeout_secs`
+    /// keeps the previous one open.
+    PerBatch,
+}
+
+impl Default for ConnectionMode {
+    fn default() -> Self {
+        ConnectionMode::PerBatch
+    }
+}
+
+/// How long a persistent connection is idle before keepalive probes are
+/// sent.
+const KEEPALIVE: Duration = Duration::from_secs(60);
+
 #[derive(Clone)]
 struct TcpConnector {
     host: String,
     port: u16,
     resolver: Resolver,
     tls: MaybeTlsSettings,
+    tos: Option<u8>,
+    priority: Option<u32>,
+    keepalive: Option<Duration>,
 }
 
 #[derive(Debug, Snafu)]
 pub enum TcpError {
     #[snafu(display("Connect error: {}", source))]
     ConnectError { source: TlsError },
+    #[snafu(display("Connection not established within {:?}", timeout))]
+    ConnectTimeout { timeout: Duration },
     #[snafu(display("Unable to resolve DNS: {}", source))]
     DnsError { source: crate::dns::DnsError },
     #[snafu(display("No addresses returned."))]
@@ -64,7 +103,12 @@ pub enum TcpError {
 
 impl TcpSinkConfig {
     pub fn new(address: String) -> Self {
-        Self { address, tls: None }
+        Self {
+            address,
+            tls: None,
+            tos: None,
+            priority: None,
+        }
     }
 
     fn build_connector(&self, cx: SinkContext) -> crate::Result<TcpConnector> {
@@ -75,7 +119,8 @@ impl TcpSinkConfig {
 
         let tls = MaybeTlsSettings::from_config(&self.tls, false)?;
 
-        let connector = TcpConnector::new(host, port, cx.resolver(), tls);
+        let connector =
+            TcpConnector::new(host, port, cx.resolver(), tls).with_qos(self.tos, self.priority);
 
         Ok(connector)
     }
@@ -83,7 +128,16 @@ impl TcpSinkConfig {
     pub fn build_service(&self, cx: SinkContext) -> crate::Result<(TcpService, Healthcheck)> {
         let connector = self.build_connector(cx)?;
         let healthcheck = connector.healthcheck();
-        Ok((connector.into(), healthcheck))
+        let service = TcpService {
+            connector,
+            connection_mode: ConnectionMode::default(),
+            idle_timeout: None,
+            write_coalescing: true,
+            connect_timeout: None,
+            connection: Arc::new(Mutex::new(None)),
+            connection_hook: None,
+        };
+        Ok((service, healthcheck))
     }
 
     pub fn build(
@@ -108,14 +162,31 @@ impl TcpConnector {
             port,
             resolver,
             tls,
+            tos: None,
+            priority: None,
+            keepalive: None,
         }
     }
 
+    fn with_qos(mut self, tos: Option<u8>, priority: Option<u32>) -> Self {
+        self.tos = tos;
+        self.priority = priority;
+        self
+    }
+
+    fn with_keepalive(mut self, keepalive: Option<Duration>) -> Self {
+        self.keepalive = keepalive;
+        self
+    }
+
     fn connect(&self) -> BoxFuture<'static, Result<TcpOrTlsStream, TcpError>> {
         let host = self.host.clone();
         let port = self.port;
         let resolver = self.resolver;
         let tls = self.tls.clone();
+        let tos = self.tos;
+        let priority = self.priority;
+        let keepalive = self.keepalive;
 
         async move {
             let ip = resolver
@@ -127,6 +198,17 @@ impl TcpConnector {
 
             let addr = SocketAddr::new(ip, port);
             let stream = tls.connect(host, addr).await.context(ConnectError)?;
+            if let Some(tos) = tos {
+                set_tos(stream.get_ref(), addr, tos);
+            }
+            if let Some(priority) = priority {
+                set_priority(stream.get_ref(), priority);
+            }
+            if let Some(keepalive) = keepalive {
+                if let Err(error) = stream.get_ref().set_keepalive(Some(keepalive)) {
+                    warn!(message = "Unable to enable keepalive on the TCP connection.", %error);
+                }
+            }
             Ok(FramedWrite::new(stream, BytesCodec::new()))
         }
         .boxed()
@@ -135,6 +217,110 @@ impl TcpConnector {
     fn healthcheck(&self) -> BoxFuture<'static, crate::Result<()>> {
         self.connect().map_ok(|_| ()).map_err(Into::into).boxed()
     }
+
+    async fn connect_within(&self, timeout: Option<Duration>) -> Result<TcpOrTlsStream, TcpError> {
+        match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, self.connect())
+                .await
+                .map_err(|_| TcpError::ConnectTimeout { timeout })?,
+            None => self.connect().await,
+        }
+    }
+}
+
+/// Marks the packets of `stream` with `tos`. Like `set_priority`, this is
+/// best-effort: a failure is logged and the connection is used unmarked.
+fn set_tos(stream: &TcpStream, addr: SocketAddr, tos: u8) {
+    #[cfg(unix)]
+    let result = match addr {
+        SocketAddr::V4(_) => setsockopt(stream, libc::IPPROTO_IP, libc::IP_TOS, tos.into()),
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        SocketAddr::V6(_) => setsockopt(stream, libc::IPPROTO_IPV6, libc::IPV6_TCLASS, tos.into()),
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        SocketAddr::V6(_) => Err(unsupported()),
+    };
+    #[cfg(not(unix))]
+    let result = {
+        let _ = (stream, addr);
+        Err(unsupported())
+    };
+
+    if let Err(error) = result {
+        warn!(message = "Unable to set the TOS of the TCP connection.", tos, %error);
+    }
+}
+
+fn set_priority(stream: &TcpStream, priority: u32) {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    let result = setsockopt(
+        stream,
+        libc::SOL_SOCKET,
+        libc::SO_PRIORITY,
+        priority as libc::c_int,
+    );
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    let result = {
+        let _ = stream;
+        Err(unsupported())
+    };
+
+    if let Err(error) = result {
+        warn!(message = "Unable to set the priority of the TCP connection.", priority, %error);
+    }
+}
+
+#[cfg(unix)]
+pub(super) fn setsockopt(
+    socket: &impl AsRawFd,
+    level: libc::c_int,
+    name: libc::c_int,
+    value: libc::c_int,
+) -> std::io::Result<()> {
+    // Safe as the option value outlives the call, and its size is passed.
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            level,
+            name,
+            &value as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+#[cfg(unix)]
+pub(super) fn getsockopt(
+    socket: &impl AsRawFd,
+    level: libc::c_int,
+    name: libc::c_int,
+) -> std::io::Result<libc::c_int> {
+    let mut value: libc::c_int = 0;
+    let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+    // Safe as the option value outlives the call, and its size is passed.
+    let result = unsafe {
+        libc::getsockopt(
+            socket.as_raw_fd(),
+            level,
+            name,
+            &mut value as *mut libc::c_int as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if result == 0 {
+        Ok(value)
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+#[allow(dead_code)]
+pub(super) fn unsupported() -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Other, "not supported on this platform")
 }
 
 impl Into<TcpSink> for TcpConnector {
@@ -143,14 +329,59 @@ impl Into<TcpSink> for TcpConnector {
     }
 }
 
-impl Into<TcpService> for TcpConnector {
-    fn into(self) -> TcpService {
-        TcpService { connector: self }
+pub struct TcpService {
+    connector: TcpConnector,
+    connection_mode: ConnectionMode,
+    idle_timeout: Option<Duration>,
+    write_coalescing: bool,
+    connect_timeout: Option<Duration>,
+    connection: Arc<Mutex<Option<IdleConnection>>>,
+    connection_hook: Option<ConnectionHook>,
+}
+
+impl TcpService {
+    /// Whether batches share one connection or each get their own.
+    pub fn with_connection_mode(mut self, connection_mode: ConnectionMode) -> Self {
+        let keepalive = match connection_mode {
+            ConnectionMode::Persistent => Some(KEEPALIVE),
+            ConnectionMode::PerBatch => None,
+        };
+        self.connector = self.connector.with_keepalive(keepalive);
+        self.connection_mode = connection_mode;
+        self
+    }
+
+    /// In `per_batch` mode, keeps the connection open between batches
+    /// until it has been idle for `idle_timeout`.
+    pub fn with_idle_timeout(mut self, idle_timeout: Option<Duration>) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    /// Whether a batch is written to the connection as a single buffer, or
+    /// line by line.
+    pub fn with_write_coalescing(mut self, write_coalescing: bool) -> Self {
+        self.write_coalescing = write_coalescing;
+        self
+    }
+
+    /// Bounds each connection attempt, including DNS resolution and the
+    /// TLS handshake.
+    pub fn with_connect_timeout(mut self, connect_timeout: Option<Duration>) -> Self {
+        self.connect_timeout = connect_timeout;
+        self
+    }
+
+    pub fn with_connection_hook(mut self, hook: ConnectionHook) -> Self {
+        self.connection_hook = Some(hook);
+        self
     }
 }
 
-pub struct TcpService {
-    connector: TcpConnector,
+struct IdleConnection {
+    stream: TcpOrTlsStream,
+    guard: ConnectionGuard,
+    last_used: Instant,
 }
 
 impl tower::Service<Bytes> for TcpService {
@@ -163,17 +394,99 @@ impl tower::Service<Bytes> for TcpService {
     }
 
     fn call(&mut self, msg: Bytes) -> Self::Future {
-        use futures::SinkExt;
         let connector = self.connector.clone();
+        let connection_mode = self.connection_mode;
+        let idle_timeout = self.idle_timeout;
+        let write_coalescing = self.write_coalescing;
+        let connect_timeout = self.connect_timeout;
+        let connection = Arc::clone(&self.connection);
+        let hook = self.connection_hook.clone();
         async move {
-            let mut connection = connector.connect().await?;
-            connection.send(msg).await.context(SendError)?;
+            // A persistent connection is never closed for being idle.
+            let idle_timeout = match (connection_mode, idle_timeout) {
+                (ConnectionMode::Persistent, _) => None,
+                (ConnectionMode::PerBatch, Some(idle_timeout)) => Some(idle_timeout),
+                (ConnectionMode::PerBatch, None) => {
+                    let mut stream = connector.connect_within(connect_timeout).await?;
+                    let _guard = ConnectionGuard::opened(&hook);
+                    return send_batch(&mut stream, msg, write_coalescing).await;
+                }
+            };
+
+            let mut slot = connection.lock().await;
+            let (mut stream, guard) = match slot.take() {
+                Some(idle)
+                    if idle_timeout.map_or(true, |timeout| idle.last_used.elapsed() < timeout) =>
+                {
+                    (idle.stream, idle.guard)
+                }
+                _ => {
+                    let stream = connector.connect_within(connect_timeout).await?;
+                    (stream, ConnectionGuard::opened(&hook))
+                }
+            };
+            send_batch(&mut stream, msg, write_coalescing).await?;
+            *slot = Some(IdleConnection {
+                stream,
+                guard,
+                last_used: Instant::now(),
+            });
+            drop(slot);
+
+            let idle_timeout = match idle_timeout {
+                Some(idle_timeout) => idle_timeout,
+                None => return Ok(()),
+            };
+            tokio::spawn(async move {
+                delay_for(idle_timeout).await;
+                let mut slot = connection.lock().await;
+                let idle = slot
+                    .as_ref()
+                    .map_or(false, |idle| idle.last_used.elapsed() >= idle_timeout);
+                if idle {
+                    debug!(message = "Closing idle connection.");
+                    *slot = None;
+                }
+            });
+
             Ok(())
         }
         .boxed()
     }
 }
 
+async fn send_batch(
+    stream: &mut TcpOrTlsStream,
+    msg: Bytes,
+    write_coalescing: bool,
+) -> Result<(), TcpError> {
+    use futures::SinkExt;
+    if write_coalescing {
+        stream.send(msg).await.context(SendError)
+    } else {
+        for line in split_lines(msg) {
+            stream.send(line).await.context(SendError)?;
+        }
+        Ok(())
+    }
+}
+
+/// Splits a buffer of newline terminated lines into one buffer per line.
+fn split_lines(msg: Bytes) -> Vec<Bytes> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    for (i, byte) in msg.iter().enumerate() {
+        if *byte == b'\n' {
+            lines.push(msg.slice(start..=i));
+            start = i + 1;
+        }
+    }
+    if start < msg.len() {
+        lines.push(msg.slice(start..));
+    }
+    lines
+}
+
 pub struct TcpSink {
     connector: TcpConnector,
     state: TcpSinkState,
@@ -353,7 +666,8 @@ impl Sink for TcpSink {
 mod test {
     use super::*;
     use crate::test_util::*;
-    use tokio::net::TcpListener;
+    use tokio::{io::AsyncReadExt, net::TcpListener};
+    use tower::Service;
 
     #[tokio::test]
     async fn healthcheck() {
@@ -381,4 +695,188 @@ mod test {
 
         assert!(bad_healthcheck.await.is_err());
     }
+
+    #[tokio::test]
+    async fn service_times_out_connecting() {
+        trace_init();
+
+        // The listener never answers the TLS handshake, so the connection
+        // is never established.
+        let addr = next_addr();
+        let _listener = TcpListener::bind(&addr).await.unwrap();
+
+        let config = TcpSinkConfig {
+            tls: Some(TlsConfig::enabled()),
+            ..TcpSinkConfig::new(addr.to_string())
+        };
+        let (service, _healthcheck) = config.build_service(SinkContext::new_test()).unwrap();
+        let mut service = service.with_connect_timeout(Some(Duration::from_secs(1)));
+
+        let error =
+            tokio::time::timeout(Duration::from_secs(5), service.call(Bytes::from("foo\n")))
+                .await
+                .expect("connect_timeout_secs was not applied")
+                .unwrap_err();
+        assert!(
+            matches!(error, TcpError::ConnectTimeout { .. }),
+            "{}",
+            error
+        );
+    }
+
+    #[tokio::test]
+    async fn service_closes_idle_connection() {
+        trace_init();
+
+        let addr = next_addr();
+        let mut listener = TcpListener::bind(&addr).await.unwrap();
+
+        let config = TcpSinkConfig::new(addr.to_string());
+        let (service, _healthcheck) = config.build_service(SinkContext::new_test()).unwrap();
+        let mut service = service.with_idle_timeout(Some(Duration::from_secs(1)));
+
+        service.call(Bytes::from("first\n")).await.unwrap();
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut buf = [0u8; 6];
+        socket.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"first\n");
+
+        // The service closes the connection once it has been idle for the timeout.
+        let mut rest = Vec::new();
+        let read = tokio::time::timeout(Duration::from_secs(5), socket.read_to_end(&mut rest))
+            .await
+            .expect("idle connection was not closed");
+        assert_eq!(read.unwrap(), 0);
+
+        service.call(Bytes::from("second\n")).await.unwrap();
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut buf = [0u8; 7];
+        socket.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"second\n");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn marks_connection_for_qos() {
+        trace_init();
+
+        let addr = next_addr();
+        let _listener = TcpListener::bind(&addr).await.unwrap();
+
+        let connector =
+            TcpConnector::new(addr.ip().to_string(), addr.port(), Resolver, None.into())
+                .with_qos(Some(0xb8), Some(4));
+        let stream = connector.connect().await.unwrap();
+        let socket = stream.get_ref().get_ref();
+
+        assert_eq!(
+            getsockopt(socket, libc::IPPROTO_IP, libc::IP_TOS).unwrap(),
+            0xb8
+        );
+        assert_eq!(
+            getsockopt(socket, libc::SOL_SOCKET, libc::SO_PRIORITY).unwrap(),
+            4
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn enables_keepalive() {
+        trace_init();
+
+        let addr = next_addr();
+        let _listener = TcpListener::bind(&addr).await.unwrap();
+
+        let connector =
+            TcpConnector::new(addr.ip().to_string(), addr.port(), Resolver, None.into())
+                .with_keepalive(Some(KEEPALIVE));
+        let stream = connector.connect().await.unwrap();
+        let socket = stream.get_ref().get_ref();
+
+        assert_eq!(
+            getsockopt(socket, libc::SOL_SOCKET, libc::SO_KEEPALIVE).unwrap(),
+            1
+        );
+        assert_eq!(
+            getsockopt(socket, libc::IPPROTO_TCP, libc::TCP_KEEPIDLE).unwrap(),
+            KEEPALIVE.as_secs() as libc::c_int
+        );
+    }
+
+    /// Sends two batches with `connection_mode`, returning what each
+    /// accepted connection received.
+    async fn received_per_connection(connection_mode: ConnectionMode) -> Vec<Vec<u8>> {
+        let addr = next_addr();
+        let mut listener = TcpListener::bind(&addr).await.unwrap();
+
+        let config = TcpSinkConfig::new(addr.to_string());
+        let (service, _healthcheck) = config.build_service(SinkContext::new_test()).unwrap();
+        let mut service = service.with_connection_mode(connection_mode);
+        service.call(Bytes::from("first\n")).await.unwrap();
+        service.call(Bytes::from("second\n")).await.unwrap();
+        drop(service);
+
+        let mut received = Vec::new();
+        while let Ok(accepted) =
+            tokio::time::timeout(Duration::from_millis(200), listener.accept()).await
+        {
+            let (mut socket, _) = accepted.unwrap();
+            let mut buf = Vec::new();
+            socket.read_to_end(&mut buf).await.unwrap();
+            received.push(buf);
+        }
+        received
+    }
+
+    #[tokio::test]
+    async fn service_reuses_persistent_connection() {
+        trace_init();
+
+        assert_eq!(
+            received_per_connection(ConnectionMode::Persistent).await,
+            vec![b"first\nsecond\n".to_vec()]
+        );
+    }
+
+    #[tokio::test]
+    async fn service_connects_per_batch() {
+        trace_init();
+
+        assert_eq!(
+            received_per_connection(ConnectionMode::PerBatch).await,
+            vec![b"first\n".to_vec(), b"second\n".to_vec()]
+        );
+    }
+
+    #[test]
+    fn split_batch_lines() {
+        assert_eq!(
+            split_lines(Bytes::from("a:1|c\nb:2|g\nc")),
+            vec![
+                Bytes::from("a:1|c\n"),
+                Bytes::from("b:2|g\n"),
+                Bytes::from("c")
+            ]
+        );
+        assert!(split_lines(Bytes::new()).is_empty());
+    }
+
+    #[tokio::test]
+    async fn service_writes_batch_as_single_buffer() {
+        trace_init();
+
+        let addr = next_addr();
+        let mut listener = TcpListener::bind(&addr).await.unwrap();
+
+        let config = TcpSinkConfig::new(addr.to_string());
+        let (mut service, _healthcheck) = config.build_service(SinkContext::new_test()).unwrap();
+
+        let batch = Bytes::from("a:1|c\nb:2|c\nc:3|c\n");
+        service.call(batch.clone()).await.unwrap();
+
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut received = Vec::new();
+        socket.read_to_end(&mut received).await.unwrap();
+        assert_eq!(Bytes::from(received), batch);
+    }
 }
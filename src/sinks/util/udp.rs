@@ -1,18 +1,36 @@
-use super::{encode_event, encoding::EncodingConfig, Encoding, SinkBuildError, StreamSinkOld};
+use super::{
+    encode_event,
+    encoding::EncodingConfig,
+    socks5::{ProxyConfig, Relay},
+    srv::{self, SrvResolver},
+    tcp, Encoding, SinkBuildError, StreamSinkOld,
+};
 use crate::{
     config::SinkContext,
     dns::Resolver,
-    internal_events::UdpSendIncomplete,
+    internal_events::{
+        Sampler, UdpDnsResolved, UdpOversizedDatagram, UdpSendFailed, UdpSendFailedDetail,
+        UdpSendIncomplete,
+    },
     sinks::{Healthcheck, VectorSink},
 };
 use bytes::Bytes;
 use futures::{future::BoxFuture, FutureExt, TryFutureExt};
-use futures01::{stream::iter_ok, Async, AsyncSink, Future, Poll as Poll01, Sink, StartSend};
+use futures01::{
+    stream::iter_ok, try_ready, Async, AsyncSink, Future, Poll as Poll01, Sink, StartSend,
+};
+#[cfg(unix)]
+use nix::libc;
 use serde::{Deserialize, Serialize};
 use snafu::{ResultExt, Snafu};
+use std::collections::{BTreeMap, VecDeque};
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex,
+};
 use std::task::{Context, Poll};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::time::{delay_for, Delay};
 use tokio_retry::strategy::ExponentialBackoff;
 
@@ -28,32 +46,204 @@ pub enum UdpError {
     NoAddresses,
     #[snafu(display("Unable to resolve DNS: {}", source))]
     DnsError { source: crate::dns::DnsError },
+    #[snafu(display("Probe datagram was rejected: {}", source))]
+    ProbeRejected { source: std::io::Error },
+    #[snafu(display("SOCKS5 proxy error: {}", source))]
+    ProxyError { source: std::io::Error },
+    #[snafu(display("Unable to look up SRV record: {}", source))]
+    SrvError {
+        source: trust_dns_resolver::error::ResolveError,
+    },
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct UdpSinkConfig {
     pub address: String,
+    /// Whether `address` is the name of an SRV record, which gives the host
+    /// and port to send to, instead of a `host:port`.
+    #[serde(default)]
+    pub srv: bool,
+    /// Host names that are mapped to a fixed IP instead of being resolved.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub static_hosts: BTreeMap<String, IpAddr>,
+    /// Whether the healthcheck sends an empty datagram, to detect a closed
+    /// port from the ICMP error it provokes.
+    #[serde(default)]
+    pub startup_probe: bool,
+    /// Whether each datagram goes to the next of the host's resolved
+    /// addresses, instead of always the first.
+    #[serde(default)]
+    pub round_robin: bool,
+    /// How often the host is resolved again, so a connection follows
+    /// changes of its address. Otherwise it is only resolved again after
+    /// a failure.
+    pub dns_refresh_interval_secs: Option<u64>,
+    /// The size of the kernel send buffer (`SO_SNDBUF`) of the socket, when
+    /// not the OS default.
+    pub send_buffer_bytes: Option<usize>,
+    /// Datagrams larger than this are counted as oversized before being
+    /// sent, to help size batches to the path MTU.
+    pub max_datagram_size: Option<usize>,
+    /// Logs the details of every this many failed sends, which are
+    /// otherwise only counted and logged in a rate limited summary.
+    pub detailed_error_sample_rate: Option<u64>,
+    /// Whether the streaming `UdpSink` stops when the host doesn't exist,
+    /// instead of retrying it like other resolution failures.
+    #[serde(default)]
+    pub fail_on_unknown_host: bool,
+    /// A SOCKS5 proxy that datagrams are relayed through, instead of being
+    /// sent directly.
+    pub proxy: Option<ProxyConfig>,
+    /// Only used by the streaming `UdpSink`.
+    #[serde(default)]
+    pub acknowledgement: Acknowledgement,
+    /// How long the streaming `UdpSink` waits before reconnecting after a
+    /// failure.
+    #[serde(default)]
+    pub backoff: BackoffConfig,
+}
+
+/// An exponential backoff: the `n`th retry in a row waits
+/// `base ^ n * factor` milliseconds, up to `max_delay_secs`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackoffConfig {
+    pub base: u64,
+    pub factor: u64,
+    pub max_delay_secs: u64,
+}
+
+impl Default for BackoffConfig {
+    fn default() -> Self {
+        Self {
+            base: 2,
+            factor: 250,
+            max_delay_secs: 60,
+        }
+    }
+}
+
+#[derive(Debug, Snafu)]
+enum UdpBuildError {
+    #[snafu(display("`{}` is not supported by this sink", option))]
+    UnsupportedOption { option: &'static str },
+}
+
+#[derive(Debug, Snafu)]
+enum BackoffConfigError {
+    #[snafu(display("`backoff.{}` must be greater than zero", option))]
+    NotPositive { option: &'static str },
+}
+
+impl BackoffConfig {
+    /// Rejects values that would make the sink retry without any delay.
+    fn validate(&self) -> Result<(), BackoffConfigError> {
+        let values = [
+            ("base", self.base),
+            ("factor", self.factor),
+            ("max_delay_secs", self.max_delay_secs),
+        ];
+        match values.iter().find(|(_, value)| *value == 0) {
+            Some((option, _)) => Err(BackoffConfigError::NotPositive { option: *option }),
+            None => Ok(()),
+        }
+    }
+
+    fn strategy(&self) -> ExponentialBackoff {
+        ExponentialBackoff::from_millis(self.base)
+            .factor(self.factor)
+            .max_delay(Duration::from_secs(self.max_delay_secs))
+    }
+}
+
+/// When the streaming UDP sink acknowledges events. UDP can't confirm
+/// delivery, so neither mode means the events were received.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Acknowledgement {
+    /// Once the datagram has been handed to the OS. While the socket can't
+    /// be connected, the sink applies backpressure.
+    OnSend,
+    /// As soon as the event is queued in the sink, up to `MAX_QUEUED`
+    /// events. The queue is lost if Vector stops before it is sent.
+    OnEnqueue,
+}
+
+impl Default for Acknowledgement {
+    fn default() -> Self {
+        Acknowledgement::OnSend
+    }
 }
 
+const MAX_QUEUED: usize = 1024;
+
 impl UdpSinkConfig {
     pub fn new(address: String) -> Self {
-        Self { address }
+        Self {
+            address,
+            srv: false,
+            static_hosts: BTreeMap::new(),
+            startup_probe: false,
+            round_robin: false,
+            dns_refresh_interval_secs: None,
+            send_buffer_bytes: None,
+            max_datagram_size: None,
+            detailed_error_sample_rate: None,
+            fail_on_unknown_host: false,
+            proxy: None,
+            acknowledgement: Acknowledgement::default(),
+            backoff: BackoffConfig::default(),
+        }
     }
 
     fn build_connector(&self, cx: SinkContext) -> crate::Result<(UdpConnector, Healthcheck)> {
-        let uri = self.address.parse::<http::Uri>()?;
-
-        let host = uri.host().ok_or(SinkBuildError::MissingHost)?.to_string();
-        let port = uri.port_u16().ok_or(SinkBuildError::MissingPort)?;
+        // The port comes from the SRV record, on every connect.
+        let (host, port) = if self.srv {
+            (self.address.clone(), 0)
+        } else {
+            let uri = self.address.parse::<http::Uri>()?;
+            let host = uri.host().ok_or(SinkBuildError::MissingHost)?.to_string();
+            let port = uri.port_u16().ok_or(SinkBuildError::MissingPort)?;
+            (host, port)
+        };
 
-        let connector = UdpConnector::new(host, port, cx.resolver());
-        let healthcheck = connector.healthcheck();
+        let static_ips = self.static_hosts.get(&host).copied().into_iter().collect();
+        let connector = UdpConnector::new(host, port, static_ips, cx.resolver())
+            .with_round_robin(self.round_robin)
+            .with_dns_refresh_interval(self.dns_refresh_interval_secs.map(Duration::from_secs))
+            .with_send_buffer_bytes(self.send_buffer_bytes)
+            .with_max_datagram_size(self.max_datagram_size)
+            .with_detailed_errors(self.detailed_error_sample_rate)
+            .with_proxy(self.proxy.clone())
+            .with_srv(if self.srv {
+                Some(SrvResolver::default())
+            } else {
+                None
+            });
+        let healthcheck = connector.healthcheck(self.startup_probe);
 
         Ok((connector, healthcheck))
     }
 
+    /// The first of the options set that only the streaming `UdpSink`
+    /// supports, which the batched `UdpService` would silently ignore.
+    fn unsupported_batched_option(&self) -> Option<&'static str> {
+        if self.acknowledgement != Acknowledgement::default() {
+            Some("acknowledgement")
+        } else if self.fail_on_unknown_host {
+            Some("fail_on_unknown_host")
+        } else if self.backoff != BackoffConfig::default() {
+            Some("backoff")
+        } else {
+            None
+        }
+    }
+
     pub fn build_service(&self, cx: SinkContext) -> crate::Result<(UdpService, Healthcheck)> {
+        if let Some(option) = self.unsupported_batched_option() {
+            return Err(UdpBuildError::UnsupportedOption { option }.into());
+        }
         let (connector, healthcheck) = self.build_connector(cx)?;
         Ok((connector.into(), healthcheck))
     }
@@ -63,8 +253,11 @@ impl UdpSinkConfig {
         cx: SinkContext,
         encoding: EncodingConfig<Encoding>,
     ) -> crate::Result<(VectorSink, Healthcheck)> {
+        self.backoff.validate()?;
         let (connector, healthcheck) = self.build_connector(cx.clone())?;
-        let sink: UdpSink = connector.into();
+        let sink = UdpSink::with_connector(connector, self.acknowledgement)
+            .with_fail_on_unknown_host(self.fail_on_unknown_host)
+            .with_backoff(self.backoff);
         let sink = StreamSinkOld::new(sink, cx.acker())
             .with_flat_map(move |event| iter_ok(encode_event(event, &encoding)));
 
@@ -76,61 +269,338 @@ impl UdpSinkConfig {
 struct UdpConnector {
     host: String,
     port: u16,
+    /// Used instead of resolving `host` when not empty.
+    static_ips: Vec<IpAddr>,
     resolver: Resolver,
+    /// The index of the next connection to send on when load balancing
+    /// across the resolved addresses, shared by all clones.
+    round_robin: Option<Arc<AtomicUsize>>,
+    /// How long a connection is used before resolving the host again.
+    dns_refresh_interval: Option<Duration>,
+    /// The address most recently sent to, shared by all clones.
+    current_address: Arc<Mutex<Option<SocketAddr>>>,
+    proxy: Option<ProxyConfig>,
+    /// When set, `host` is an SRV record to look up the target in.
+    srv: Option<SrvResolver>,
+    send_buffer_bytes: Option<usize>,
+    max_datagram_size: Option<usize>,
+    detailed_errors: Option<DetailedErrors>,
+}
+
+/// Picks the send failures whose details are logged: every `rate`th one,
+/// counted across all clones.
+#[derive(Clone)]
+struct DetailedErrors {
+    rate: u64,
+    failures: Arc<Sampler>,
+}
+
+impl DetailedErrors {
+    fn new(rate: u64) -> Self {
+        Self {
+            rate,
+            failures: Arc::new(Sampler::new()),
+        }
+    }
+
+    fn sample(&self) -> bool {
+        self.failures.sample_at(self.rate).is_some()
+    }
+}
+
+/// Reports a failure to send `datagram` to `address`, in detail when it's
+/// sampled by `detailed_errors`.
+fn send_failed(
+    detailed_errors: Option<&DetailedErrors>,
+    address: Option<SocketAddr>,
+    error: &std::io::Error,
+    datagram: &[u8],
+) {
+    emit!(UdpSendFailed { error });
+    if detailed_errors.map_or(false, DetailedErrors::sample) {
+        emit!(UdpSendFailedDetail {
+            address,
+            error,
+            datagram,
+        });
+    }
+}
+
+/// A socket connected to the target, or to a proxy relaying to it.
+struct UdpConnection<S> {
+    socket: S,
+    relay: Option<Relay>,
+    /// The address `socket` is connected to.
+    address: SocketAddr,
+}
+
+impl UdpConnection<UdpSocket> {
+    fn send(&self, datagram: &[u8]) -> std::io::Result<usize> {
+        match &self.relay {
+            Some(relay) => self
+                .socket
+                .send(&relay.frame(datagram))
+                .map(|sent| sent.saturating_sub(relay.overhead())),
+            None => self.socket.send(datagram),
+        }
+    }
 }
 
 impl UdpConnector {
-    fn new(host: String, port: u16, resolver: Resolver) -> Self {
+    fn new(host: String, port: u16, static_ips: Vec<IpAddr>, resolver: Resolver) -> Self {
         Self {
             host,
             port,
+            static_ips,
             resolver,
+            round_robin: None,
+            dns_refresh_interval: None,
+            current_address: Arc::new(Mutex::new(None)),
+            proxy: None,
+            srv: None,
+            send_buffer_bytes: None,
+            max_datagram_size: None,
+            detailed_errors: None,
+        }
+    }
+
+    /// The address datagrams are currently sent to, once resolved.
+    fn current_address(&self) -> Option<SocketAddr> {
+        *self.current_address.lock().unwrap()
+    }
+
+    fn with_round_robin(mut self, round_robin: bool) -> Self {
+        self.round_robin = if round_robin {
+            Some(Arc::new(AtomicUsize::new(0)))
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Picks which of `connections` the next datagram is sent on, and
+    /// records its address as the current one.
+    fn pick<S>(&self, connections: &[UdpConnection<S>]) -> usize {
+        let index = match &self.round_robin {
+            Some(next) => next.fetch_add(1, Ordering::Relaxed) % connections.len(),
+            None => 0,
+        };
+        *self.current_address.lock().unwrap() = Some(connections[index].address);
+        index
+    }
+
+    fn with_dns_refresh_interval(mut self, dns_refresh_interval: Option<Duration>) -> Self {
+        self.dns_refresh_interval = dns_refresh_interval;
+        self
+    }
+
+    /// Whether a connection made after resolving the host at `resolved_at`
+    /// is due to be replaced.
+    fn needs_refresh(&self, resolved_at: Instant) -> bool {
+        self.dns_refresh_interval
+            .map_or(false, |interval| resolved_at.elapsed() >= interval)
+    }
+
+    fn with_proxy(mut self, proxy: Option<ProxyConfig>) -> Self {
+        self.proxy = proxy;
+        self
+    }
+
+    fn with_srv(mut self, srv: Option<SrvResolver>) -> Self {
+        self.srv = srv;
+        self
+    }
+
+    fn with_send_buffer_bytes(mut self, send_buffer_bytes: Option<usize>) -> Self {
+        self.send_buffer_bytes = send_buffer_bytes;
+        self
+    }
+
+    fn with_max_datagram_size(mut self, max_datagram_size: Option<usize>) -> Self {
+        self.max_datagram_size = max_datagram_size;
+        self
+    }
+
+    /// Counts `datagram` as oversized if it exceeds `max_datagram_size`.
+    fn check_size(&self, datagram: &[u8]) {
+        if let Some(max_datagram_size) = self.max_datagram_size {
+            if datagram.len() > max_datagram_size {
+                emit!(UdpOversizedDatagram {
+                    byte_size: datagram.len(),
+                    max_datagram_size,
+                });
+            }
         }
     }
 
-    fn connect(&self) -> BoxFuture<'static, Result<UdpSocket, UdpError>> {
+    fn with_detailed_errors(mut self, sample_rate: Option<u64>) -> Self {
+        self.detailed_errors = sample_rate.map(DetailedErrors::new);
+        self
+    }
+
+    /// Resolves the host and connects a socket to each of its addresses
+    /// when load balancing, or else only to the first one.
+    fn connect(&self) -> BoxFuture<'static, Result<Vec<UdpConnection<UdpSocket>>, UdpError>> {
         let host = self.host.clone();
         let port = self.port;
+        let static_ips = self.static_ips.clone();
         let resolver = self.resolver;
+        let round_robin = self.round_robin.is_some();
+        let current_address = Arc::clone(&self.current_address);
+        let proxy = self.proxy.clone();
+        let srv = self.srv;
+        let send_buffer_bytes = self.send_buffer_bytes;
 
         async move {
-            let ip = resolver
-                .lookup_ip(host.clone())
-                .await
-                .context(DnsError)?
-                .next()
-                .ok_or(UdpError::NoAddresses)?;
+            let (host, port) = match srv {
+                Some(srv) => {
+                    let records = srv.lookup(&host).await.context(SrvError)?;
+                    let record = srv::select(&records).ok_or(UdpError::NoAddresses)?;
+                    debug!(
+                        message = "Looked up SRV record.",
+                        name = %host,
+                        target = %record.target,
+                        port = record.port,
+                    );
+                    (record.target.clone(), record.port)
+                }
+                None => (host, port),
+            };
 
-            let addr = SocketAddr::new(ip, port);
-            let bind_address = find_bind_address(&addr);
+            if let Some(proxy) = proxy {
+                let relay = Relay::associate(&proxy.address, &host, port)
+                    .await
+                    .context(ProxyError)?;
+                let socket = connect_socket(relay.address(), send_buffer_bytes)?;
+                *current_address.lock().unwrap() = Some(relay.address());
+                debug!(
+                    message = "Connected UDP socket through SOCKS5 proxy.",
+                    proxy = %proxy.address,
+                    relay = %relay.address(),
+                );
+                return Ok(vec![UdpConnection {
+                    socket,
+                    address: relay.address(),
+                    relay: Some(relay),
+                }]);
+            }
+
+            let mut ips: Vec<IpAddr> = if static_ips.is_empty() {
+                let start = Instant::now();
+                let ips = resolver
+                    .lookup_ip(host.clone())
+                    .await
+                    .context(DnsError)?
+                    .collect();
+                emit!(UdpDnsResolved {
+                    duration: start.elapsed()
+                });
+                ips
+            } else {
+                static_ips
+            };
+            if ips.is_empty() {
+                return Err(UdpError::NoAddresses);
+            }
+
+            if !round_robin {
+                ips.truncate(1);
+            }
 
-            let socket = UdpSocket::bind(bind_address).context(BindError)?;
-            socket.connect(addr).context(ConnectError)?;
+            let connections = ips
+                .into_iter()
+                .map(|ip| {
+                    let address = SocketAddr::new(ip, port);
+                    connect_socket(address, send_buffer_bytes).map(|socket| UdpConnection {
+                        socket,
+                        relay: None,
+                        address,
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            *current_address.lock().unwrap() = Some(connections[0].address);
 
-            Ok(socket)
+            Ok(connections)
         }
         .boxed()
     }
 
-    fn healthcheck(&self) -> BoxFuture<'static, crate::Result<()>> {
-        self.connect().map_ok(|_| ()).map_err(|e| e.into()).boxed()
+    fn healthcheck(&self, startup_probe: bool) -> BoxFuture<'static, crate::Result<()>> {
+        self.connect()
+            .and_then(move |connections| async move {
+                for connection in connections {
+                    // A proxy relays datagrams rather than forwarding the
+                    // ICMP error a probe relies on.
+                    if startup_probe && connection.relay.is_none() {
+                        probe(connection.socket).await?;
+                    }
+                }
+                Ok(())
+            })
+            .map_err(|e| e.into())
+            .boxed()
+    }
+}
+
+fn connect_socket(
+    address: SocketAddr,
+    send_buffer_bytes: Option<usize>,
+) -> Result<UdpSocket, UdpError> {
+    let socket = UdpSocket::bind(find_bind_address(&address)).context(BindError)?;
+    if let Some(bytes) = send_buffer_bytes {
+        set_send_buffer(&socket, bytes);
     }
+    socket.connect(address).context(ConnectError)?;
+    Ok(socket)
+}
+
+/// Sends an empty datagram and waits briefly for the ICMP port unreachable
+/// error it provokes when nothing is listening. No error doesn't guarantee
+/// delivery, as the error may be filtered or arrive late.
+async fn probe(socket: UdpSocket) -> Result<(), UdpError> {
+    tokio::task::spawn_blocking(move || {
+        socket.send(&[]).context(ProbeRejected)?;
+        socket
+            .set_read_timeout(Some(Duration::from_millis(100)))
+            .context(ProbeRejected)?;
+        match socket.recv(&mut [0; 1]) {
+            Err(error) if error.kind() == std::io::ErrorKind::ConnectionRefused => {
+                Err(UdpError::ProbeRejected { source: error })
+            }
+            _ => Ok(()),
+        }
+    })
+    .await
+    .expect("UDP probe panicked")
 }
 
 impl Into<UdpSink> for UdpConnector {
     fn into(self) -> UdpSink {
-        UdpSink::new(self.host, self.port, self.resolver)
+        UdpSink::with_connector(self, Acknowledgement::default())
     }
 }
 
 impl Into<UdpService> for UdpConnector {
     fn into(self) -> UdpService {
-        UdpService { connector: self }
+        UdpService {
+            connector: self,
+            connections: Arc::new(Mutex::new(None)),
+        }
     }
 }
 
 pub struct UdpService {
     connector: UdpConnector,
+    /// The connections reused across calls, with when they were made, until
+    /// sending on one fails or the DNS refresh interval elapses.
+    connections: Arc<Mutex<Option<(Arc<Vec<UdpConnection<UdpSocket>>>, Instant)>>>,
+}
+
+impl UdpService {
+    /// The address datagrams are currently sent to, once resolved.
+    pub fn current_address(&self) -> Option<SocketAddr> {
+        self.connector.current_address()
+    }
 }
 
 impl tower::Service<Bytes> for UdpService {
@@ -144,9 +614,34 @@ impl tower::Service<Bytes> for UdpService {
 
     fn call(&mut self, msg: Bytes) -> Self::Future {
         let connector = self.connector.clone();
+        let cached = Arc::clone(&self.connections);
         async move {
-            let socket = connector.connect().await?;
-            socket.send(&msg).context(SendError)?;
+            let reused = cached
+                .lock()
+                .unwrap()
+                .clone()
+                .filter(|(_, resolved_at)| !connector.needs_refresh(*resolved_at));
+            let connections = match reused {
+                Some((connections, _)) => connections,
+                None => {
+                    let connections = Arc::new(connector.connect().await?);
+                    *cached.lock().unwrap() = Some((Arc::clone(&connections), Instant::now()));
+                    connections
+                }
+            };
+            let connection = &connections[connector.pick(&connections)];
+            connector.check_size(&msg);
+            if let Err(error) = connection.send(&msg) {
+                // The next call reconnects, picking up any change of address.
+                cached.lock().unwrap().take();
+                send_failed(
+                    connector.detailed_errors.as_ref(),
+                    Some(connection.address),
+                    &error,
+                    &msg,
+                );
+                return Err(UdpError::SendError { source: error });
+            }
             Ok(())
         }
         .boxed()
@@ -158,36 +653,66 @@ pub struct UdpSink {
     state: State,
     span: tracing::Span,
     backoff: ExponentialBackoff,
+    acknowledgement: Acknowledgement,
+    queue: VecDeque<Bytes>,
+    fail_on_unknown_host: bool,
+    /// When the host of the current connections was resolved.
+    resolved_at: Option<Instant>,
 }
 
+type Connections = Vec<UdpConnection<tokio::net::UdpSocket>>;
+
 enum State {
     Initializing,
-    Connecting(Box<dyn Future<Item = UdpSocket, Error = UdpError> + Send>),
-    Connected(UdpSocket),
+    Connecting(Box<dyn Future<Item = Connections, Error = UdpError> + Send>),
+    Connected(Connections),
+    /// A datagram is being sent on one of the connections, which are handed
+    /// back once it is. A failed datagram is handed back to be retried.
+    Sending(Box<dyn Future<Item = Connections, Error = Bytes> + Send>),
     Backoff(Box<dyn Future<Item = (), Error = ()> + Send>),
 }
 
 impl UdpSink {
     pub fn new(host: String, port: u16, resolver: Resolver) -> Self {
-        let span = info_span!("connection", %host, %port);
-        let connector = UdpConnector {
-            host,
-            port,
-            resolver,
-        };
+        Self::with_connector(
+            UdpConnector::new(host, port, Vec::new(), resolver),
+            Acknowledgement::default(),
+        )
+    }
+
+    fn with_connector(connector: UdpConnector, acknowledgement: Acknowledgement) -> Self {
+        let span = info_span!("connection", host = %connector.host, port = %connector.port);
         Self {
             connector,
             state: State::Initializing,
             span,
-            backoff: Self::fresh_backoff(),
+            backoff: BackoffConfig::default().strategy(),
+            acknowledgement,
+            queue: VecDeque::new(),
+            fail_on_unknown_host: false,
+            resolved_at: None,
         }
     }
 
-    fn fresh_backoff() -> ExponentialBackoff {
-        // TODO: make configurable
-        ExponentialBackoff::from_millis(2)
-            .factor(250)
-            .max_delay(Duration::from_secs(60))
+    /// The address datagrams are currently sent to, once resolved.
+    pub fn current_address(&self) -> Option<SocketAddr> {
+        self.connector.current_address()
+    }
+
+    fn with_fail_on_unknown_host(mut self, fail_on_unknown_host: bool) -> Self {
+        self.fail_on_unknown_host = fail_on_unknown_host;
+        self
+    }
+
+    fn with_backoff(mut self, backoff: BackoffConfig) -> Self {
+        self.backoff = backoff.strategy();
+        self
+    }
+
+    fn needs_refresh(&self) -> bool {
+        self.resolved_at.map_or(false, |resolved_at| {
+            self.connector.needs_refresh(resolved_at)
+        })
     }
 
     fn next_delay(&mut self) -> Delay {
@@ -199,21 +724,70 @@ impl UdpSink {
         Box::new(async move { Ok(delay.await) }.boxed().compat())
     }
 
-    fn poll_socket(&mut self) -> Poll01<&mut UdpSocket, ()> {
+    fn connect(&self) -> Box<dyn Future<Item = Connections, Error = UdpError> + Send> {
+        let connections = self.connector.connect().and_then(|connections| async move {
+            connections
+                .into_iter()
+                .map(
+                    |UdpConnection {
+                         socket,
+                         relay,
+                         address,
+                     }| {
+                        tokio::net::UdpSocket::from_std(socket)
+                            .context(ConnectError)
+                            .map(|socket| UdpConnection {
+                                socket,
+                                relay,
+                                address,
+                            })
+                    },
+                )
+                .collect::<Result<Connections, _>>()
+        });
+        Box::new(connections.boxed().compat())
+    }
+
+    fn poll_socket(&mut self) -> Poll01<(), ()> {
         loop {
             self.state = match self.state {
-                State::Initializing => {
-                    State::Connecting(Box::new(self.connector.connect().compat()))
-                }
+                State::Initializing => State::Connecting(self.connect()),
                 State::Connecting(ref mut fut) => match fut.poll() {
                     Ok(Async::NotReady) => return Ok(Async::NotReady),
-                    Ok(Async::Ready(socket)) => State::Connected(socket),
+                    Ok(Async::Ready(connections)) => {
+                        self.resolved_at = Some(Instant::now());
+                        State::Connected(connections)
+                    }
+                    Err(UdpError::DnsError { source })
+                        if self.fail_on_unknown_host && source.is_not_found() =>
+                    {
+                        error!(
+                            message = "Host doesn't exist; stopping.",
+                            host = %self.connector.host,
+                            error = %source
+                        );
+                        // The failed future can't be polled again.
+                        self.state = State::Initializing;
+                        return Err(());
+                    }
                     Err(error) => {
                         error!(message = "unable to connect UDP socket", %error);
                         State::Backoff(self.next_delay01())
                     }
                 },
-                State::Connected(ref mut socket) => return Ok(Async::Ready(socket)),
+                State::Connected(_) if self.needs_refresh() => {
+                    debug!(message = "Refreshing DNS resolution.", host = %self.connector.host);
+                    State::Initializing
+                }
+                State::Connected(_) => return Ok(Async::Ready(())),
+                State::Sending(ref mut fut) => match fut.poll() {
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Ok(Async::Ready(connections)) => State::Connected(connections),
+                    Err(line) => {
+                        self.queue.push_front(line);
+                        State::Backoff(self.next_delay01())
+                    }
+                },
                 State::Backoff(ref mut delay) => match delay.poll() {
                     Ok(Async::NotReady) => return Ok(Async::NotReady),
                     Ok(Async::Ready(())) => State::Initializing,
@@ -224,6 +798,69 @@ impl UdpSink {
     }
 }
 
+impl UdpSink {
+    /// Starts sending `line` once the socket is connected and no other
+    /// datagram is in flight. The send itself completes asynchronously.
+    fn send_line(&mut self, line: Bytes) -> Result<AsyncSink<Bytes>, ()> {
+        match self.poll_socket()? {
+            Async::Ready(()) => {
+                let mut connections = match std::mem::replace(&mut self.state, State::Initializing)
+                {
+                    State::Connected(connections) => connections,
+                    _ => unreachable!(),
+                };
+                let index = self.connector.pick(&connections);
+                debug!(
+                    message = "sending event.",
+                    bytes = %line.len()
+                );
+                self.connector.check_size(&line);
+                let (datagram, overhead) = match &connections[index].relay {
+                    Some(relay) => (relay.frame(&line), relay.overhead()),
+                    None => (line.clone(), 0),
+                };
+                let detailed_errors = self.connector.detailed_errors.clone();
+                let address = connections[index].address;
+                let send = async move {
+                    match connections[index].socket.send(&datagram).await {
+                        Ok(sent) => {
+                            if sent != datagram.len() {
+                                emit!(UdpSendIncomplete {
+                                    data_size: line.len(),
+                                    sent: sent.saturating_sub(overhead),
+                                });
+                            }
+                            Ok(connections)
+                        }
+                        Err(error) => {
+                            send_failed(detailed_errors.as_ref(), Some(address), &error, &line);
+                            Err(line)
+                        }
+                    }
+                };
+                self.state = State::Sending(Box::new(send.boxed().compat()));
+                // Most sends complete right away; otherwise this registers
+                // the task to be woken once the send completes.
+                let _ = self.poll_socket();
+                Ok(AsyncSink::Ready)
+            }
+            Async::NotReady => Ok(AsyncSink::NotReady(line)),
+        }
+    }
+
+    /// Sends queued lines until the queue is empty or the socket isn't
+    /// ready, returning whether the queue was emptied.
+    fn send_queued(&mut self) -> Result<bool, ()> {
+        while let Some(line) = self.queue.pop_front() {
+            if let AsyncSink::NotReady(line) = self.send_line(line)? {
+                self.queue.push_front(line);
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
 impl Sink for UdpSink {
     type SinkItem = Bytes;
     type SinkError = ();
@@ -232,36 +869,67 @@ impl Sink for UdpSink {
         let span = self.span.clone();
         let _enter = span.enter();
 
-        match self.poll_socket() {
-            Ok(Async::Ready(socket)) => {
-                debug!(
-                    message = "sending event.",
-                    bytes = %line.len()
-                );
-                match socket.send(&line) {
-                    Err(error) => {
-                        self.state = State::Backoff(self.next_delay01());
-                        error!(message = "send failed", %error);
-                        Ok(AsyncSink::NotReady(line))
-                    }
-                    Ok(sent) => {
-                        if sent != line.len() {
-                            emit!(UdpSendIncomplete {
-                                data_size: line.len(),
-                                sent,
-                            });
-                        }
-                        Ok(AsyncSink::Ready)
-                    }
+        match self.acknowledgement {
+            // Lines that failed to send are queued for a retry, ahead of
+            // any new line.
+            Acknowledgement::OnSend if self.send_queued()? => self.send_line(line),
+            Acknowledgement::OnSend => Ok(AsyncSink::NotReady(line)),
+            Acknowledgement::OnEnqueue => {
+                self.send_queued()?;
+                if self.queue.len() >= MAX_QUEUED {
+                    return Ok(AsyncSink::NotReady(line));
                 }
+                self.queue.push_back(line);
+                self.send_queued()?;
+                Ok(AsyncSink::Ready)
             }
-            Ok(Async::NotReady) => Ok(AsyncSink::NotReady(line)),
-            Err(_) => unreachable!(),
         }
     }
 
     fn poll_complete(&mut self) -> Poll01<(), Self::SinkError> {
-        Ok(Async::Ready(()))
+        let span = self.span.clone();
+        let _enter = span.enter();
+
+        let sent = self.send_queued()?;
+        match self.acknowledgement {
+            // Queued events are already acknowledged, so they don't hold up
+            // completion.
+            Acknowledgement::OnEnqueue => Ok(Async::Ready(())),
+            Acknowledgement::OnSend if sent => {
+                if let State::Sending(_) = self.state {
+                    try_ready!(self.poll_socket());
+                }
+                Ok(Async::Ready(()))
+            }
+            Acknowledgement::OnSend => Ok(Async::NotReady),
+        }
+    }
+}
+
+/// Sizes the kernel send buffer of `socket`. The OS may refuse or clamp
+/// the size, which is only logged as the socket works either way.
+fn set_send_buffer(socket: &UdpSocket, requested: usize) {
+    #[cfg(unix)]
+    let result = {
+        let value = requested.min(libc::c_int::max_value() as usize) as libc::c_int;
+        tcp::setsockopt(socket, libc::SOL_SOCKET, libc::SO_SNDBUF, value)
+            .and_then(|()| tcp::getsockopt(socket, libc::SOL_SOCKET, libc::SO_SNDBUF))
+    };
+    #[cfg(not(unix))]
+    let result: std::io::Result<i32> = {
+        let _ = socket;
+        Err(tcp::unsupported())
+    };
+
+    match result {
+        Ok(actual) if (actual as usize) < requested => debug!(
+            message = "UDP send buffer size was clamped.",
+            requested, actual
+        ),
+        Ok(_) => (),
+        Err(error) => {
+            debug!(message = "Unable to set the UDP send buffer size.", requested, %error)
+        }
     }
 }
 
@@ -271,3 +939,586 @@ fn find_bind_address(remote_addr: &SocketAddr) -> SocketAddr {
         SocketAddr::V6(_) => SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 0),
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        buffers::Acker,
+        test_util::{next_addr, next_addr_v6},
+    };
+    use futures::compat::Future01CompatExt;
+    use tower::Service;
+
+    async fn send_and_receive(config: UdpSinkConfig, addr: SocketAddr) -> Vec<u8> {
+        let mut receiver = tokio::net::UdpSocket::bind(addr).await.unwrap();
+        let (mut service, _) = config.build_service(SinkContext::new_test()).unwrap();
+        service.call(Bytes::from("foo\n")).await.unwrap();
+
+        let mut buf = [0; 16];
+        let len = receiver.recv(&mut buf).await.unwrap();
+        buf[..len].to_vec()
+    }
+
+    #[tokio::test]
+    async fn static_host_bypasses_resolver() {
+        let addr = next_addr();
+        let mut config = UdpSinkConfig::new(format!("statsd.invalid:{}", addr.port()));
+        config
+            .static_hosts
+            .insert("statsd.invalid".into(), addr.ip());
+
+        assert_eq!(send_and_receive(config, addr).await, b"foo\n");
+    }
+
+    #[tokio::test]
+    async fn unmapped_host_is_resolved() {
+        let addr = next_addr();
+        let mut config = UdpSinkConfig::new(format!("localhost:{}", addr.port()));
+        config
+            .static_hosts
+            .insert("statsd.invalid".into(), "192.0.2.1".parse().unwrap());
+
+        assert_eq!(send_and_receive(config, addr).await, b"foo\n");
+    }
+
+    #[tokio::test]
+    async fn startup_probe_detects_closed_port() {
+        let mut config = UdpSinkConfig::new(next_addr().to_string());
+        config.startup_probe = true;
+        let (_, healthcheck) = config.build_service(SinkContext::new_test()).unwrap();
+
+        let error = healthcheck.await.unwrap_err();
+        assert!(
+            error.to_string().starts_with("Probe datagram was rejected"),
+            "{}",
+            error
+        );
+    }
+
+    #[tokio::test]
+    async fn startup_probe_passes_open_port() {
+        let addr = next_addr();
+        let _receiver = tokio::net::UdpSocket::bind(addr).await.unwrap();
+        let mut config = UdpSinkConfig::new(addr.to_string());
+        config.startup_probe = true;
+        let (_, healthcheck) = config.build_service(SinkContext::new_test()).unwrap();
+
+        healthcheck.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn healthcheck_resolves_host() {
+        // Without a probe, nothing needs to listen on the port.
+        let config = UdpSinkConfig::new(format!("localhost:{}", next_addr().port()));
+        let (_, healthcheck) = config.build_service(SinkContext::new_test()).unwrap();
+        healthcheck.await.unwrap();
+
+        let nameserver = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let srv = SrvResolver::with_nameserver(nameserver.local_addr().unwrap());
+        tokio::spawn(srv::test::mock_nameserver(nameserver, vec![]));
+        let connector =
+            UdpConnector::new("_statsd._udp.example.com".to_owned(), 0, vec![], Resolver)
+                .with_srv(Some(srv));
+        let error = connector.healthcheck(false).await.unwrap_err();
+        assert_eq!(error.to_string(), "No addresses returned.");
+    }
+
+    // Other loopback addresses than 127.0.0.1 are only usable out of the box
+    // on Linux.
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn round_robin_rotates_addresses() {
+        let port = next_addr().port();
+        let first = SocketAddr::new([127, 0, 0, 1].into(), port);
+        let second = SocketAddr::new([127, 0, 0, 2].into(), port);
+        let mut receivers = vec![
+            tokio::net::UdpSocket::bind(first).await.unwrap(),
+            tokio::net::UdpSocket::bind(second).await.unwrap(),
+        ];
+
+        let connector = UdpConnector::new(
+            "statsd.invalid".into(),
+            port,
+            vec![first.ip(), second.ip()],
+            Resolver,
+        )
+        .with_round_robin(true);
+        let mut service: UdpService = connector.into();
+        for _ in 0..4 {
+            service.call(Bytes::from("foo\n")).await.unwrap();
+        }
+
+        for receiver in &mut receivers {
+            let mut buf = [0; 16];
+            let mut sources = Vec::new();
+            for _ in 0..2 {
+                let (len, source) =
+                    tokio::time::timeout(Duration::from_secs(1), receiver.recv_from(&mut buf))
+                        .await
+                        .expect("Datagram not received")
+                        .unwrap();
+                assert_eq!(&buf[..len], b"foo\n");
+                sources.push(source);
+            }
+            // Each address is sent to on a socket reused across datagrams.
+            assert_eq!(sources[0], sources[1]);
+        }
+    }
+
+    fn counter_value(name: &str) -> f64 {
+        let controller = crate::metrics::get_controller().unwrap();
+        crate::metrics::capture_metrics(controller)
+            .map(crate::Event::into_metric)
+            .filter(|metric| metric.name == name)
+            .map(|metric| match metric.value {
+                crate::event::metric::MetricValue::Counter { value } => value,
+                _ => 0.0,
+            })
+            .sum()
+    }
+
+    // Linux reports the ICMP error provoked by a datagram to a closed port
+    // on the next send.
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn counts_send_errors() {
+        let _ = crate::metrics::init();
+        let errors = counter_value("connection_send_errors_total");
+
+        let config = UdpSinkConfig::new(next_addr().to_string());
+        let (mut service, _) = config.build_service(SinkContext::new_test()).unwrap();
+        service.call(Bytes::from("foo\n")).await.unwrap();
+        delay_for(Duration::from_millis(50)).await;
+        let error = service.call(Bytes::from("foo\n")).await.unwrap_err();
+        assert!(matches!(error, UdpError::SendError { .. }), "{}", error);
+
+        // Other tests may record metrics concurrently.
+        assert!(counter_value("connection_send_errors_total") >= errors + 1.0);
+    }
+
+    #[tokio::test]
+    async fn counts_oversized_datagrams() {
+        let _ = crate::metrics::init();
+        let addr = next_addr();
+        let mut receiver = tokio::net::UdpSocket::bind(addr).await.unwrap();
+        let mut config = UdpSinkConfig::new(addr.to_string());
+        config.max_datagram_size = Some(8);
+        let (mut service, _) = config.build_service(SinkContext::new_test()).unwrap();
+        let oversized = counter_value("oversized_datagrams_total");
+
+        service.call(Bytes::from("foo")).await.unwrap();
+        service.call(Bytes::from("foo bar baz")).await.unwrap();
+        assert_eq!(counter_value("oversized_datagrams_total"), oversized + 1.0);
+
+        // Oversized datagrams are still sent.
+        let mut buf = [0; 16];
+        receiver.recv(&mut buf).await.unwrap();
+        let len = receiver.recv(&mut buf).await.unwrap();
+        assert_eq!(&buf[..len], b"foo bar baz");
+    }
+
+    fn histogram_samples(name: &str) -> usize {
+        let controller = crate::metrics::get_controller().unwrap();
+        crate::metrics::capture_metrics(controller)
+            .map(crate::Event::into_metric)
+            .filter(|metric| metric.name == name)
+            .map(|metric| match metric.value {
+                crate::event::metric::MetricValue::Distribution { values, .. } => values.len(),
+                _ => 0,
+            })
+            .sum()
+    }
+
+    #[test]
+    fn samples_detailed_errors() {
+        let mut config = UdpSinkConfig::new("127.0.0.1:8125".into());
+        config.detailed_error_sample_rate = Some(3);
+        let (connector, _) = config.build_connector(SinkContext::new_test()).unwrap();
+        let detailed_errors = connector.detailed_errors.unwrap();
+
+        let sampled = (0..7)
+            .map(|_| detailed_errors.clone().sample())
+            .collect::<Vec<_>>();
+        assert_eq!(sampled, vec![false, false, true, false, false, true, false]);
+    }
+
+    #[test]
+    fn uses_configured_backoff() {
+        let connector = UdpConnector::new("localhost".into(), 8125, Vec::new(), Resolver);
+        let mut sink = UdpSink::with_connector(connector, Acknowledgement::OnSend).with_backoff(
+            BackoffConfig {
+                base: 10,
+                factor: 5,
+                max_delay_secs: 1,
+            },
+        );
+        assert_eq!(sink.backoff.next(), Some(Duration::from_millis(50)));
+        assert_eq!(sink.backoff.next(), Some(Duration::from_millis(500)));
+        assert_eq!(sink.backoff.next(), Some(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn service_rejects_streaming_options() {
+        let mut config = UdpSinkConfig::new("127.0.0.1:8125".into());
+        config.fail_on_unknown_host = true;
+
+        let error = match config.build_service(SinkContext::new_test()) {
+            Ok(_) => panic!("Config with `fail_on_unknown_host` was accepted"),
+            Err(error) => error,
+        };
+        assert_eq!(
+            error.to_string(),
+            "`fail_on_unknown_host` is not supported by this sink"
+        );
+    }
+
+    #[test]
+    fn rejects_zero_backoff() {
+        let mut config = UdpSinkConfig::new("127.0.0.1:8125".into());
+        config.backoff.factor = 0;
+
+        let error = match config.build(SinkContext::new_test(), Encoding::Text.into()) {
+            Ok(_) => panic!("Backoff without delay was accepted"),
+            Err(error) => error,
+        };
+        assert_eq!(
+            error.to_string(),
+            "`backoff.factor` must be greater than zero"
+        );
+    }
+
+    #[test]
+    fn parses_send_buffer_bytes() {
+        let config: UdpSinkConfig = toml::from_str(
+            r#"
+            address = "127.0.0.1:8125"
+            send_buffer_bytes = 65536
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.send_buffer_bytes, Some(65536));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn sizes_send_buffer() {
+        let addr = next_addr();
+        let mut config = UdpSinkConfig::new(addr.to_string());
+        config.send_buffer_bytes = Some(65536);
+        let (connector, _) = config.build_connector(SinkContext::new_test()).unwrap();
+
+        let connection = connector.connect().await.unwrap().remove(0);
+        let actual =
+            tcp::getsockopt(&connection.socket, libc::SOL_SOCKET, libc::SO_SNDBUF).unwrap();
+        // Linux doubles the size to leave room for its own bookkeeping.
+        assert!(actual >= 65536);
+    }
+
+    #[tokio::test]
+    async fn binds_ipv6_socket_for_ipv6_host() {
+        let addr = next_addr_v6();
+        let mut receiver = tokio::net::UdpSocket::bind(addr).await.unwrap();
+        let config = UdpSinkConfig::new(addr.to_string());
+        let (connector, _) = config.build_connector(SinkContext::new_test()).unwrap();
+
+        let connection = connector.connect().await.unwrap().remove(0);
+        assert!(connection.socket.local_addr().unwrap().is_ipv6());
+        assert_eq!(connector.current_address(), Some(addr));
+
+        connection.send(b"foo").unwrap();
+        let mut buf = [0; 16];
+        let len = receiver.recv(&mut buf).await.unwrap();
+        assert_eq!(&buf[..len], b"foo");
+    }
+
+    #[tokio::test]
+    async fn service_reuses_connection() {
+        let addr = next_addr();
+        let mut receiver = tokio::net::UdpSocket::bind(addr).await.unwrap();
+        let config = UdpSinkConfig::new(addr.to_string());
+        let (mut service, _) = config.build_service(SinkContext::new_test()).unwrap();
+
+        let mut sources = Vec::new();
+        for _ in 0..3 {
+            service.call(Bytes::from("foo\n")).await.unwrap();
+            let mut buf = [0; 16];
+            let (_, source) = receiver.recv_from(&mut buf).await.unwrap();
+            sources.push(source);
+        }
+        // Every datagram came from the same socket.
+        assert!(sources.iter().all(|source| *source == sources[0]));
+    }
+
+    #[tokio::test]
+    async fn times_dns_resolution() {
+        let _ = crate::metrics::init();
+        let before = histogram_samples("dns_resolution_duration_nanoseconds");
+
+        let connector = UdpConnector::new("localhost".into(), 8125, Vec::new(), Resolver);
+        connector.connect().await.unwrap();
+
+        // Other tests may resolve hosts concurrently.
+        assert!(histogram_samples("dns_resolution_duration_nanoseconds") >= before + 1);
+    }
+
+    /// Accepts UDP associations without authentication, relaying through
+    /// the returned socket, which receives the framed datagrams.
+    async fn mock_socks5_proxy(addr: SocketAddr) -> tokio::net::UdpSocket {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let relay = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let relay_port = relay.local_addr().unwrap().port().to_be_bytes();
+        let mut listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut control, _) = listener.accept().await.unwrap();
+                let mut greeting = [0; 3];
+                control.read_exact(&mut greeting).await.unwrap();
+                assert_eq!(greeting, [5, 1, 0]);
+                control.write_all(&[5, 0]).await.unwrap();
+
+                let mut request = [0; 10];
+                control.read_exact(&mut request).await.unwrap();
+                assert_eq!(&request[..2], &[5, 3]);
+                let mut reply = vec![5, 0, 0, 1, 127, 0, 0, 1];
+                reply.extend_from_slice(&relay_port);
+                control.write_all(&reply).await.unwrap();
+
+                // The association lasts until the client hangs up.
+                tokio::spawn(async move {
+                    let _ = control.read(&mut [0; 1]).await;
+                });
+            }
+        });
+        relay
+    }
+
+    #[tokio::test]
+    async fn relays_through_socks5_proxy() {
+        let proxy = next_addr();
+        let mut relay = mock_socks5_proxy(proxy).await;
+
+        let mut config = UdpSinkConfig::new("statsd.invalid:8125".into());
+        config.proxy = Some(ProxyConfig {
+            address: proxy.to_string(),
+        });
+        let (mut service, healthcheck) = config.build_service(SinkContext::new_test()).unwrap();
+        healthcheck.await.unwrap();
+        service.call(Bytes::from("foo\n")).await.unwrap();
+
+        // Datagrams are only sent to the relay, and the proxy resolves the
+        // target's name.
+        let mut buf = [0; 64];
+        let len = tokio::time::timeout(Duration::from_secs(1), relay.recv(&mut buf))
+            .await
+            .expect("Datagram not relayed")
+            .unwrap();
+        assert_eq!(
+            &buf[..len],
+            &b"\x00\x00\x00\x03\x0estatsd.invalid\x1f\xbdfoo\n"[..]
+        );
+    }
+
+    #[tokio::test]
+    async fn exposes_current_address() {
+        let addr = next_addr();
+        let _receiver = tokio::net::UdpSocket::bind(addr).await.unwrap();
+        let config = UdpSinkConfig::new(format!("localhost:{}", addr.port()));
+        let (mut service, _) = config.build_service(SinkContext::new_test()).unwrap();
+        assert_eq!(service.current_address(), None);
+
+        service.call(Bytes::from("foo\n")).await.unwrap();
+        assert_eq!(service.current_address(), Some(addr));
+    }
+
+    #[tokio::test]
+    async fn targets_srv_record() {
+        let addr = next_addr();
+        let mut receiver = tokio::net::UdpSocket::bind(addr).await.unwrap();
+        let nameserver = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let srv = SrvResolver::with_nameserver(nameserver.local_addr().unwrap());
+        tokio::spawn(srv::test::mock_nameserver(
+            nameserver,
+            vec![srv::SrvRecord {
+                priority: 10,
+                weight: 1,
+                port: addr.port(),
+                target: "localhost".to_owned(),
+            }],
+        ));
+
+        let connector =
+            UdpConnector::new("_statsd._udp.example.com".to_owned(), 0, vec![], Resolver)
+                .with_srv(Some(srv));
+        let mut service: UdpService = connector.into();
+        service.call(Bytes::from("foo\n")).await.unwrap();
+        assert_eq!(service.current_address(), Some(addr));
+
+        let mut buf = [0; 16];
+        let len = receiver.recv(&mut buf).await.unwrap();
+        assert_eq!(&buf[..len], b"foo\n");
+    }
+
+    /// A connector to an SRV record whose first lookup points to `first`
+    /// and later ones to `second`, refreshed every 100ms.
+    async fn changing_srv_connector(first: SocketAddr, second: SocketAddr) -> UdpConnector {
+        let nameserver = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let srv = SrvResolver::with_nameserver(nameserver.local_addr().unwrap());
+        let answers = vec![first, second, second]
+            .into_iter()
+            .map(|addr| {
+                vec![srv::SrvRecord {
+                    priority: 10,
+                    weight: 1,
+                    port: addr.port(),
+                    target: "localhost".to_owned(),
+                }]
+            })
+            .collect();
+        tokio::spawn(srv::test::mock_nameserver_sequence(nameserver, answers));
+
+        UdpConnector::new("_statsd._udp.example.com".to_owned(), 0, vec![], Resolver)
+            .with_srv(Some(srv))
+            .with_dns_refresh_interval(Some(Duration::from_millis(100)))
+    }
+
+    async fn receive(receiver: &mut tokio::net::UdpSocket) -> Vec<u8> {
+        let mut buf = [0; 16];
+        let len = tokio::time::timeout(Duration::from_secs(1), receiver.recv(&mut buf))
+            .await
+            .expect("Datagram not received")
+            .unwrap();
+        buf[..len].to_vec()
+    }
+
+    #[tokio::test]
+    async fn service_refreshes_dns() {
+        let (first, second) = (next_addr(), next_addr());
+        let mut first_receiver = tokio::net::UdpSocket::bind(first).await.unwrap();
+        let mut second_receiver = tokio::net::UdpSocket::bind(second).await.unwrap();
+        let mut service: UdpService = changing_srv_connector(first, second).await.into();
+
+        service.call(Bytes::from("foo")).await.unwrap();
+        service.call(Bytes::from("bar")).await.unwrap();
+        assert_eq!(receive(&mut first_receiver).await, b"foo");
+        assert_eq!(receive(&mut first_receiver).await, b"bar");
+
+        delay_for(Duration::from_millis(150)).await;
+        service.call(Bytes::from("baz")).await.unwrap();
+        assert_eq!(receive(&mut second_receiver).await, b"baz");
+        assert_eq!(service.current_address(), Some(second));
+    }
+
+    #[tokio::test]
+    async fn sink_refreshes_dns() {
+        let (first, second) = (next_addr(), next_addr());
+        let mut first_receiver = tokio::net::UdpSocket::bind(first).await.unwrap();
+        let mut second_receiver = tokio::net::UdpSocket::bind(second).await.unwrap();
+        let sink: UdpSink = changing_srv_connector(first, second).await.into();
+
+        let sink = sink.send(Bytes::from("foo")).compat().await.unwrap();
+        let sink = sink.send(Bytes::from("bar")).compat().await.unwrap();
+        assert_eq!(receive(&mut first_receiver).await, b"foo");
+        assert_eq!(receive(&mut first_receiver).await, b"bar");
+
+        delay_for(Duration::from_millis(150)).await;
+        let sink = sink.send(Bytes::from("baz")).compat().await.unwrap();
+        assert_eq!(receive(&mut second_receiver).await, b"baz");
+        assert_eq!(sink.current_address(), Some(second));
+    }
+
+    #[tokio::test]
+    async fn sends_asynchronously() {
+        let addr = next_addr();
+        let mut receiver = tokio::net::UdpSocket::bind(addr).await.unwrap();
+
+        let (acker, ack_counter) = Acker::new_for_testing();
+        let connector = UdpConnector::new(addr.ip().to_string(), addr.port(), Vec::new(), Resolver);
+        let sink = StreamSinkOld::new(
+            UdpSink::with_connector(connector, Acknowledgement::OnSend),
+            acker,
+        );
+
+        let lines = (0..100).map(|i| Bytes::from(format!("line {}", i)));
+        let _ = sink
+            .send_all(iter_ok::<_, ()>(lines))
+            .compat()
+            .await
+            .unwrap();
+        assert_eq!(ack_counter.load(Ordering::Relaxed), 100);
+
+        let mut buf = [0; 16];
+        for i in 0..100 {
+            let len = tokio::time::timeout(Duration::from_secs(1), receiver.recv(&mut buf))
+                .await
+                .expect("Datagram not received")
+                .unwrap();
+            assert_eq!(&buf[..len], format!("line {}", i).as_bytes());
+        }
+    }
+
+    /// Fails connecting with the resolver error `error`, returning an
+    /// error if the sink stopped, or else whether it is backing off.
+    async fn after_dns_error(
+        fail_on_unknown_host: bool,
+        error: crate::dns::DnsError,
+    ) -> Result<bool, ()> {
+        let connector = UdpConnector::new("statsd.invalid".into(), 8125, Vec::new(), Resolver);
+        let mut sink = UdpSink::with_connector(connector, Acknowledgement::OnSend)
+            .with_fail_on_unknown_host(fail_on_unknown_host);
+        let error = UdpError::DnsError { source: error };
+        sink.state = State::Connecting(Box::new(futures01::future::err::<Connections, _>(error)));
+
+        let polled = futures01::future::lazy(|| Ok::<_, ()>(sink.poll_socket()))
+            .compat()
+            .await
+            .unwrap();
+        polled.map(|_| matches!(sink.state, State::Backoff(_)))
+    }
+
+    #[tokio::test]
+    async fn fails_fast_on_unknown_host() {
+        // What the resolver really returns for a name that doesn't exist.
+        let nxdomain = || async {
+            Resolver
+                .lookup_ip("nonexistent.invalid".to_owned())
+                .await
+                .err()
+                .unwrap()
+        };
+        let servfail = || crate::dns::DnsError::UnableLookup {
+            source: std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "failed to lookup address information: Temporary failure in name resolution",
+            ),
+        };
+
+        assert_eq!(after_dns_error(true, nxdomain().await).await, Err(()));
+        assert_eq!(after_dns_error(true, servfail()).await, Ok(true));
+        assert_eq!(after_dns_error(false, nxdomain().await).await, Ok(true));
+    }
+
+    async fn acked_events(acknowledgement: Acknowledgement) -> usize {
+        let (acker, ack_counter) = Acker::new_for_testing();
+        let connector = UdpConnector::new("nonexistent.invalid".into(), 8125, Vec::new(), Resolver);
+        let sink = StreamSinkOld::new(UdpSink::with_connector(connector, acknowledgement), acker);
+
+        let _ = tokio::time::timeout(
+            Duration::from_millis(500),
+            sink.send(Bytes::from("foo\n")).compat(),
+        )
+        .await;
+        ack_counter.load(Ordering::Relaxed)
+    }
+
+    #[tokio::test]
+    async fn acks_on_send() {
+        assert_eq!(acked_events(Acknowledgement::OnSend).await, 0);
+    }
+
+    #[tokio::test]
+    async fn acks_on_enqueue() {
+        assert_eq!(acked_events(Acknowledgement::OnEnqueue).await, 1);
+    }
+}
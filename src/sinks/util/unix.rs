@@ -4,7 +4,10 @@ use crate::{
         UnixSocketConnectionEstablished, UnixSocketConnectionFailure, UnixSocketEventSent,
         UnixSocketFlushFailed, UnixSocketSendFailed,
     },
-    sinks::util::{encode_event, encoding::EncodingConfig, Encoding, StreamSinkOld},
+    sinks::util::{
+        encode_event, encoding::EncodingConfig, ConnectionGuard, ConnectionHook, Encoding,
+        StreamSinkOld,
+    },
     sinks::{Healthcheck, VectorSink},
 };
 use bytes::Bytes;
@@ -91,7 +94,10 @@ impl Into<UnixSink> for UnixConnector {
 
 impl Into<UnixService> for UnixConnector {
     fn into(self) -> UnixService {
-        UnixService { connector: self }
+        UnixService {
+            connector: self,
+            connection_hook: None,
+        }
     }
 }
 
@@ -238,6 +244,14 @@ impl Sink for UnixSink {
 
 pub struct UnixService {
     connector: UnixConnector,
+    connection_hook: Option<ConnectionHook>,
+}
+
+impl UnixService {
+    pub fn with_connection_hook(mut self, hook: ConnectionHook) -> Self {
+        self.connection_hook = Some(hook);
+        self
+    }
 }
 
 impl tower::Service<Bytes> for UnixService {
@@ -252,13 +266,11 @@ impl tower::Service<Bytes> for UnixService {
     fn call(&mut self, msg: Bytes) -> Self::Future {
         use futures::SinkExt;
         let connector = self.connector.clone();
+        let hook = self.connection_hook.clone();
         async move {
-            connector
-                .connect()
-                .await?
-                .send(msg)
-                .await
-                .context(SendError)
+            let mut stream = connector.connect().await?;
+            let _guard = ConnectionGuard::opened(&hook);
+            stream.send(msg).await.context(SendError)
         }
         .boxed()
     }
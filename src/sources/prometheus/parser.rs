@@ -1,9 +1,15 @@
 use crate::event::metric::{Metric, MetricKind, MetricValue};
 use indexmap::IndexMap;
-use std::collections::BTreeMap;
+use prost::Message;
+use std::{collections::BTreeMap, convert::TryFrom};
 
 pub use prometheus_parser::*;
 
+/// The Prometheus protobuf exposition format.
+pub mod proto {
+    include!(concat!(env!("OUT_DIR"), "/io.prometheus.client.rs"));
+}
+
 #[derive(Default)]
 struct AggregatedHistogram {
     buckets: Vec<f64>,
@@ -29,6 +35,15 @@ fn has_values_or_none(tags: BTreeMap<String, String>) -> Option<BTreeMap<String,
 }
 
 pub fn parse(packet: &str) -> Result<Vec<Metric>, ParserError> {
+    parse_with_type_tag(packet, None)
+}
+
+/// Parses the metrics like `parse`, additionally tagging every metric
+/// with its Prometheus type under `type_tag`, if set.
+pub fn parse_with_type_tag(
+    packet: &str,
+    type_tag: Option<&str>,
+) -> Result<Vec<Metric>, ParserError> {
     let mut result = Vec::new();
 
     for group in prometheus_parser::group_metrics(packet)? {
@@ -37,13 +52,27 @@ pub fn parse(packet: &str) -> Result<Vec<Metric>, ParserError> {
             continue;
         }
 
+        let type_name = match &group.metrics {
+            GroupKind::Counter(_) => "counter",
+            GroupKind::Gauge(_) => "gauge",
+            GroupKind::Untyped(_) => "untyped",
+            GroupKind::Histogram(_) => "histogram",
+            GroupKind::Summary(_) => "summary",
+        };
+        let make_tags = |mut tags: BTreeMap<String, String>| {
+            if let Some(type_tag) = type_tag {
+                tags.insert(type_tag.to_owned(), type_name.to_owned());
+            }
+            has_values_or_none(tags)
+        };
+
         match group.metrics {
             GroupKind::Counter(vec) => {
                 for metric in vec {
                     let counter = Metric {
                         name: group.name.clone(),
                         timestamp: None,
-                        tags: has_values_or_none(metric.labels),
+                        tags: make_tags(metric.labels),
                         kind: MetricKind::Absolute,
                         value: MetricValue::Counter {
                             value: metric.value,
@@ -58,7 +87,7 @@ pub fn parse(packet: &str) -> Result<Vec<Metric>, ParserError> {
                     let gauge = Metric {
                         name: group.name.clone(),
                         timestamp: None,
-                        tags: has_values_or_none(metric.labels),
+                        tags: make_tags(metric.labels),
                         kind: MetricKind::Absolute,
                         value: MetricValue::Gauge {
                             value: metric.value,
@@ -95,7 +124,7 @@ pub fn parse(packet: &str) -> Result<Vec<Metric>, ParserError> {
                     let hist = Metric {
                         name: group.name.clone(),
                         timestamp: None,
-                        tags: has_values_or_none(tags),
+                        tags: make_tags(tags),
                         kind: MetricKind::Absolute,
                         value: MetricValue::AggregatedHistogram {
                             buckets: aggregate.buckets,
@@ -133,7 +162,7 @@ pub fn parse(packet: &str) -> Result<Vec<Metric>, ParserError> {
                     let summary = Metric {
                         name: group.name.clone(),
                         timestamp: None,
-                        tags: has_values_or_none(tags),
+                        tags: make_tags(tags),
                         kind: MetricKind::Absolute,
                         value: MetricValue::AggregatedSummary {
                             quantiles: aggregate.quantiles,
@@ -152,11 +181,107 @@ pub fn parse(packet: &str) -> Result<Vec<Metric>, ParserError> {
     Ok(result)
 }
 
+/// Parses metrics in the length-delimited protobuf exposition format,
+/// tagging them with their type like `parse_with_type_tag`.
+pub fn parse_protobuf(mut body: &[u8], type_tag: Option<&str>) -> Result<Vec<Metric>, ParserError> {
+    let mut result = Vec::new();
+
+    while !body.is_empty() {
+        let family = proto::MetricFamily::decode_length_delimited(&mut body).map_err(|error| {
+            ParserError::InvalidProtobuf {
+                message: error.to_string(),
+            }
+        })?;
+
+        let metric_type = family.r#type();
+        let type_name = match metric_type {
+            proto::MetricType::Counter => "counter",
+            proto::MetricType::Gauge => "gauge",
+            proto::MetricType::Untyped => "untyped",
+            proto::MetricType::Histogram => "histogram",
+            proto::MetricType::Summary => "summary",
+        };
+        let name = family.name.unwrap_or_default();
+
+        for metric in family.metric {
+            let mut tags = metric
+                .label
+                .into_iter()
+                .map(|label| {
+                    (
+                        label.name.unwrap_or_default(),
+                        label.value.unwrap_or_default(),
+                    )
+                })
+                .collect::<BTreeMap<_, _>>();
+            if let Some(type_tag) = type_tag {
+                tags.insert(type_tag.to_owned(), type_name.to_owned());
+            }
+
+            let value = match metric_type {
+                proto::MetricType::Counter => MetricValue::Counter {
+                    value: metric.counter.unwrap_or_default().value(),
+                },
+                proto::MetricType::Gauge => MetricValue::Gauge {
+                    value: metric.gauge.unwrap_or_default().value(),
+                },
+                proto::MetricType::Untyped => MetricValue::Gauge {
+                    value: metric.untyped.unwrap_or_default().value(),
+                },
+                proto::MetricType::Histogram => {
+                    let histogram = metric.histogram.unwrap_or_default();
+                    let mut buckets = Vec::new();
+                    let mut counts = Vec::new();
+                    for bucket in &histogram.bucket {
+                        // last bucket is implicit, because we store its value in 'count'
+                        if bucket.upper_bound() != f64::INFINITY {
+                            buckets.push(bucket.upper_bound());
+                            counts.push(to_count(bucket.cumulative_count())?);
+                        }
+                    }
+                    MetricValue::AggregatedHistogram {
+                        buckets,
+                        counts,
+                        count: to_count(histogram.sample_count())?,
+                        sum: histogram.sample_sum(),
+                    }
+                }
+                proto::MetricType::Summary => {
+                    let summary = metric.summary.unwrap_or_default();
+                    MetricValue::AggregatedSummary {
+                        quantiles: summary.quantile.iter().map(|q| q.quantile()).collect(),
+                        values: summary.quantile.iter().map(|q| q.value()).collect(),
+                        count: to_count(summary.sample_count())?,
+                        sum: summary.sample_sum(),
+                    }
+                }
+            };
+
+            result.push(Metric {
+                name: name.clone(),
+                timestamp: None,
+                tags: has_values_or_none(tags),
+                kind: MetricKind::Absolute,
+                value,
+            });
+        }
+    }
+
+    Ok(result)
+}
+
+fn to_count(value: u64) -> Result<u32, ParserError> {
+    u32::try_from(value).map_err(|_| ParserError::ValueOutOfRange {
+        value: value as f64,
+    })
+}
+
 #[cfg(test)]
 mod test {
-    use super::parse;
+    use super::{parse, parse_protobuf, parse_with_type_tag, proto};
     use crate::event::metric::{Metric, MetricKind, MetricValue};
     use pretty_assertions::assert_eq;
+    use prost::Message;
 
     macro_rules! map {
         ($($key:expr => $value:expr),*) => {
@@ -190,6 +315,102 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_protobuf() {
+        let families = vec![
+            proto::MetricFamily {
+                name: Some("uptime".into()),
+                help: None,
+                r#type: Some(proto::MetricType::Counter as i32),
+                metric: vec![proto::Metric {
+                    label: vec![proto::LabelPair {
+                        name: Some("host".into()),
+                        value: Some("a".into()),
+                    }],
+                    counter: Some(proto::Counter { value: Some(123.0) }),
+                    ..Default::default()
+                }],
+            },
+            proto::MetricFamily {
+                name: Some("request_duration_seconds".into()),
+                help: None,
+                r#type: Some(proto::MetricType::Histogram as i32),
+                metric: vec![proto::Metric {
+                    histogram: Some(proto::Histogram {
+                        sample_count: Some(6),
+                        sample_sum: Some(9.0),
+                        bucket: vec![
+                            proto::Bucket {
+                                cumulative_count: Some(2),
+                                upper_bound: Some(1.0),
+                            },
+                            proto::Bucket {
+                                cumulative_count: Some(6),
+                                upper_bound: Some(f64::INFINITY),
+                            },
+                        ],
+                    }),
+                    ..Default::default()
+                }],
+            },
+        ];
+        let mut body = Vec::new();
+        for family in families {
+            family.encode_length_delimited(&mut body).unwrap();
+        }
+
+        assert_eq!(
+            parse_protobuf(&body, None),
+            Ok(vec![
+                Metric {
+                    name: "uptime".into(),
+                    timestamp: None,
+                    tags: Some(map! {"host" => "a"}),
+                    kind: MetricKind::Absolute,
+                    value: MetricValue::Counter { value: 123.0 },
+                },
+                Metric {
+                    name: "request_duration_seconds".into(),
+                    timestamp: None,
+                    tags: None,
+                    kind: MetricKind::Absolute,
+                    value: MetricValue::AggregatedHistogram {
+                        buckets: vec![1.0],
+                        counts: vec![2],
+                        count: 6,
+                        sum: 9.0,
+                    },
+                },
+            ]),
+        );
+        assert!(parse_protobuf(&body[..body.len() - 1], None).is_err());
+    }
+
+    #[test]
+    fn test_type_tag() {
+        let exp = r##"
+            # TYPE uptime counter
+            uptime 123.0
+            # TYPE temperature gauge
+            temperature{room="kitchen"} 21.5
+            loose 1
+            "##;
+
+        let metrics = parse_with_type_tag(exp, Some("prometheus_type")).unwrap();
+        let tags = metrics
+            .into_iter()
+            .map(|metric| metric.tags.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tags,
+            vec![
+                map! {"prometheus_type" => "counter"},
+                map! {"prometheus_type" => "gauge", "room" => "kitchen"},
+                map! {"prometheus_type" => "untyped"},
+            ]
+        );
+    }
+
     #[test]
     fn test_counter_empty() {
         let exp = r##"
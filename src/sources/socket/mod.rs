@@ -589,6 +589,40 @@ mod test {
         );
     }
 
+    #[tokio::test]
+    async fn udp_reused_buffer_keeps_events_intact() {
+        let (tx, rx) = Pipeline::new_test();
+        let address = init_udp(tx).await;
+
+        // Each packet is shorter than the one before, so any event still
+        // pointing into the receive buffer would be overwritten.
+        let long = "a".repeat(1000);
+        let medium = "b".repeat(100);
+        send_lines_udp(
+            address,
+            vec![
+                format!("{}\n{}", long, medium),
+                medium.clone(),
+                "c".to_string(),
+            ],
+        );
+        let events = collect_n(rx, 4).await.unwrap();
+
+        let messages = events
+            .iter()
+            .map(|event| event.as_log()[log_schema().message_key()].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec![
+                long.into(),
+                medium.clone().into(),
+                medium.into(),
+                "c".into()
+            ]
+        );
+    }
+
     #[tokio::test]
     async fn udp_it_includes_host() {
         let (tx, rx) = Pipeline::new_test();
@@ -55,9 +55,10 @@ pub fn udp(
                 .expect("Failed to bind to udp listener socket");
             info!(message = "Listening.", %address);
 
-            let mut buf = BytesMut::with_capacity(max_length);
+            // Every datagram is received into this one buffer, so it's only
+            // allocated and zeroed once.
+            let mut buf = vec![0; max_length];
             loop {
-                buf.resize(max_length, 0);
                 tokio::select! {
                     recv = socket.recv_from(&mut buf) => {
                         let (byte_size, address) = recv.map_err(|error| {
@@ -67,7 +68,9 @@ pub fn udp(
                             });
                         })?;
 
-                        let mut payload = buf.split_to(byte_size);
+                        // The events share the payload's allocation, so it's copied
+                        // out of the receive buffer for them, sized to the datagram.
+                        let mut payload = BytesMut::from(&buf[..byte_size]);
 
                         // UDP processes messages per payload, where messages are separated by newline
                         // and stretch to end of payload.
@@ -39,6 +39,10 @@ use tokio::{
 use tokio_util::codec::{Encoder, FramedRead, FramedWrite, LinesCodec};
 
 pub mod stats;
+mod statsd;
+
+#[cfg(all(test, feature = "sources-statsd"))]
+pub use statsd::MockStatsdServer;
 
 #[macro_export]
 macro_rules! assert_downcast_matches {
@@ -0,0 +1,166 @@
+#![cfg(all(test, feature = "sources-statsd"))]
+
+use super::wait_for;
+use crate::{event::Metric, sources::statsd::parser::parse};
+use futures::{future, StreamExt};
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    io::AsyncRead,
+    net::{TcpListener, UdpSocket},
+};
+use tokio_util::codec::{FramedRead, LinesCodec};
+
+/// A StatsD server for sinks to send to in tests. It records every line it
+/// receives, over any of the supported transports.
+#[derive(Clone, Default)]
+pub struct MockStatsdServer {
+    lines: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockStatsdServer {
+    pub async fn udp(addr: SocketAddr) -> Self {
+        let server = Self::default();
+        let mut socket = UdpSocket::bind(addr).await.unwrap();
+
+        let lines = Arc::clone(&server.lines);
+        tokio::spawn(async move {
+            let mut buf = [0; 65_536];
+            while let Ok(len) = socket.recv(&mut buf).await {
+                let packet = String::from_utf8_lossy(&buf[..len]);
+                lines
+                    .lock()
+                    .unwrap()
+                    .extend(packet.lines().map(str::to_owned));
+            }
+        });
+
+        server
+    }
+
+    pub async fn tcp(addr: SocketAddr) -> Self {
+        let server = Self::default();
+        let mut listener = TcpListener::bind(addr).await.unwrap();
+
+        let lines = Arc::clone(&server.lines);
+        tokio::spawn(async move {
+            while let Some(Ok(socket)) = listener.incoming().next().await {
+                tokio::spawn(receive_lines(socket, Arc::clone(&lines)));
+            }
+        });
+
+        server
+    }
+
+    #[cfg(unix)]
+    pub async fn unix(path: impl AsRef<std::path::Path>) -> Self {
+        let server = Self::default();
+        let mut listener = tokio::net::UnixListener::bind(path).unwrap();
+
+        let lines = Arc::clone(&server.lines);
+        tokio::spawn(async move {
+            while let Some(Ok(socket)) = listener.incoming().next().await {
+                tokio::spawn(receive_lines(socket, Arc::clone(&lines)));
+            }
+        });
+
+        server
+    }
+
+    /// The raw lines received so far.
+    pub fn lines(&self) -> Vec<String> {
+        self.lines.lock().unwrap().clone()
+    }
+
+    /// The metrics received so far. Panics if a line isn't valid StatsD.
+    pub fn metrics(&self) -> Vec<Metric> {
+        self.lines()
+            .iter()
+            .map(|line| parse(line).unwrap_or_else(|error| panic!("{:?}: {}", line, error)))
+            .collect()
+    }
+
+    /// Waits (for 5 secs) until at least `count` lines have been received.
+    pub async fn wait_for_lines(&self, count: usize) -> Vec<String> {
+        let lines = Arc::clone(&self.lines);
+        wait_for(|| future::ready(lines.lock().unwrap().len() >= count)).await;
+        self.lines()
+    }
+
+    /// Waits (for 5 secs) until at least `count` metrics have been received.
+    pub async fn wait_for_metrics(&self, count: usize) -> Vec<Metric> {
+        self.wait_for_lines(count).await;
+        self.metrics()
+    }
+}
+
+async fn receive_lines<T: AsyncRead>(socket: T, lines: Arc<Mutex<Vec<String>>>) {
+    FramedRead::new(socket, LinesCodec::new())
+        .take_while(|line| future::ready(line.is_ok()))
+        .for_each(|line| {
+            lines.lock().unwrap().push(line.unwrap());
+            future::ready(())
+        })
+        .await
+}
+
+mod test {
+    use super::*;
+    use crate::{
+        event::metric::{MetricKind, MetricValue},
+        test_util::next_addr,
+    };
+    use tokio::{io::AsyncWriteExt, net::TcpStream};
+
+    fn counter(name: &str, value: f64) -> Metric {
+        Metric {
+            name: name.to_owned(),
+            timestamp: None,
+            tags: None,
+            kind: MetricKind::Incremental,
+            value: MetricValue::Counter { value },
+        }
+    }
+
+    #[tokio::test]
+    async fn receives_udp() {
+        let addr = next_addr();
+        let server = MockStatsdServer::udp(addr).await;
+
+        let mut socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        socket.send_to(b"foo:1|c\nbar:2|c\n", addr).await.unwrap();
+
+        assert_eq!(
+            server.wait_for_metrics(2).await,
+            vec![counter("foo", 1.0), counter("bar", 2.0)]
+        );
+    }
+
+    #[tokio::test]
+    async fn receives_tcp() {
+        let addr = next_addr();
+        let server = MockStatsdServer::tcp(addr).await;
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"foo:1|c\nbar:2|c\n").await.unwrap();
+
+        assert_eq!(
+            server.wait_for_metrics(2).await,
+            vec![counter("foo", 1.0), counter("bar", 2.0)]
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn receives_unix() {
+        let path = tempfile::tempdir().unwrap().into_path().join("statsd.sock");
+        let server = MockStatsdServer::unix(path.clone()).await;
+
+        let mut stream = tokio::net::UnixStream::connect(path).await.unwrap();
+        stream.write_all(b"foo:1|c\n").await.unwrap();
+
+        assert_eq!(server.wait_for_metrics(1).await, vec![counter("foo", 1.0)]);
+    }
+}
@@ -122,6 +122,14 @@ impl MaybeTlsStream<TcpStream> {
             Self::Tls(tls) => tls.get_ref().peer_addr(),
         }
     }
+
+    /// The underlying TCP stream.
+    pub fn get_ref(&self) -> &TcpStream {
+        match self {
+            Self::Raw(raw) => raw,
+            Self::Tls(tls) => tls.get_ref(),
+        }
+    }
 }
 
 pub(crate) fn tls_connector_builder(settings: &MaybeTlsSettings) -> Result<SslConnectorBuilder> {